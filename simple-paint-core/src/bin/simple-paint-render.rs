@@ -0,0 +1,135 @@
+//! Paints a stroke file, optionally over a PNG document, and saves the
+//! result as a PNG without opening a window.
+
+use std::{
+    error::Error,
+    fs::{self, File},
+    io::{self, BufWriter, Read, Write},
+    process,
+};
+
+use simple_paint::canvas::{Canvas, Resample, TiledImage};
+use simple_paint::{png, strokes};
+
+/// Size of the canvas when there is neither a document nor a `canvas`
+/// command, the same as in the application.
+const DEFAULT_SIZE: (usize, usize) = (2000, 1000);
+
+const USAGE: &str = "\
+Usage: simple-paint-render [OPTIONS] -o OUTPUT [STROKES]
+
+Paints the commands of the stroke file STROKES, or of the standard input if
+it is `-`, and saves the image as a PNG.
+
+Options:
+  -o, --output FILE     where to save the image
+  -d, --document FILE   paint over a PNG file instead of a blank canvas
+  -s, --size WxH        scale the image to W x H pixels, e.g. for thumbnails
+  -h, --help            show this help";
+
+struct Options {
+    output: String,
+    document: Option<String>,
+    strokes: Option<String>,
+    size: Option<(usize, usize)>,
+}
+
+fn parse_size(s: &str) -> Option<(usize, usize)> {
+    let (w, h) = s.split_once('x')?;
+    let size = (w.parse().ok()?, h.parse().ok()?);
+    if size.0 > 0 && size.1 > 0 {
+        Some(size)
+    } else {
+        None
+    }
+}
+
+fn parse_args(
+    mut args: impl Iterator<Item = String>,
+) -> Result<Options, String> {
+    let mut output = None;
+    let mut document = None;
+    let mut strokes = None;
+    let mut size = None;
+    while let Some(arg) = args.next() {
+        let mut value = || {
+            args.next()
+                .ok_or_else(|| format!("`{}` needs a value", arg))
+        };
+        match arg.as_str() {
+            "-h" | "--help" => {
+                println!("{}", USAGE);
+                process::exit(0);
+            }
+            "-o" | "--output" => output = Some(value()?),
+            "-d" | "--document" => document = Some(value()?),
+            "-s" | "--size" => {
+                let s = value()?;
+                size = Some(
+                    parse_size(&s)
+                        .ok_or_else(|| format!("invalid size `{}`", s))?,
+                );
+            }
+            _ if arg.starts_with('-') && arg != "-" => {
+                return Err(format!("unknown option `{}`", arg))
+            }
+            _ if strokes.is_none() => strokes = Some(arg),
+            _ => return Err("more than one stroke file".to_string()),
+        }
+    }
+    let output = output.ok_or("no output file")?;
+    if strokes.is_none() && document.is_none() {
+        return Err("nothing to render".to_string());
+    }
+    Ok(Options {
+        output,
+        document,
+        strokes,
+        size,
+    })
+}
+
+fn run(options: Options) -> Result<(), Box<dyn Error>> {
+    let mut canvas = Canvas::new(Box::new(|_, _, _| ()), DEFAULT_SIZE);
+    if let Some(path) = &options.document {
+        let image = png::decode(File::open(path)?)
+            .map_err(|e| format!("{}: {}", path, e))?;
+        canvas.set_image(TiledImage::from_image(&image));
+    }
+    if let Some(path) = &options.strokes {
+        let text = if path == "-" {
+            let mut text = String::new();
+            io::stdin().read_to_string(&mut text)?;
+            text
+        } else {
+            fs::read_to_string(path)?
+        };
+        let commands =
+            strokes::parse(&text).map_err(|e| format!("{}: {}", path, e))?;
+        strokes::draw(&commands, &mut canvas);
+    }
+    if let Some(size) = options.size {
+        let shrinking =
+            size.0 * size.1 < canvas.get_size().0 * canvas.get_size().1;
+        let method = if shrinking {
+            Resample::Area
+        } else {
+            Resample::Bicubic
+        };
+        canvas.scale_image(size, method);
+    }
+    let mut out = BufWriter::new(File::create(&options.output)?);
+    png::encode(&canvas.image.to_image(), &mut out)?;
+    out.flush()?;
+    Ok(())
+}
+
+fn main() {
+    let result = parse_args(std::env::args().skip(1))
+        .map_err(|e| format!("{}\n\n{}", e, USAGE).into())
+        .and_then(run);
+    if let Err(e) = result {
+        eprintln!("simple-paint-render: {}", e);
+        process::exit(1);
+    }
+}
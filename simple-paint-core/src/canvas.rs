@@ -0,0 +1,1229 @@
+use std::{collections::HashMap, ops::Range};
+
+mod fill;
+mod history;
+mod layer;
+pub mod pen;
+mod resample;
+mod selection;
+pub mod shape;
+mod stabilizer;
+mod tiled;
+mod transform;
+pub use fill::FillSetting;
+pub use history::DEFAULT_MEMORY_BUDGET;
+use history::{GrowStep, History, PixelStep, Step};
+pub use layer::Layer;
+pub use pen::PenSetting;
+pub use pen::PressureCurve;
+pub use resample::Resample;
+pub use selection::{Selection, SelectionMode, SelectionShape};
+pub use stabilizer::{Stabilizer, StabilizerMode};
+pub use tiled::{TiledImage, TILE_SIZE};
+pub use transform::{Filter, Floating, Placement};
+
+/// A pixel in cairo's `Format::ARgb32`, i.e. with premultiplied alpha. The
+/// bytes are stored in the order B, G, R, A as on little-endian machines.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[allow(clippy::upper_case_acronyms)]
+pub struct RGB {
+    array: [u8; 4],
+}
+
+impl RGB {
+    pub const TRANSPARENT: RGB = RGB {
+        array: [0, 0, 0, 0],
+    };
+
+    pub const fn new(r: u8, g: u8, b: u8) -> RGB {
+        RGB {
+            array: [b, g, r, 0xff],
+        }
+    }
+
+    /// Makes a pixel from non-premultiplied components.
+    pub fn with_alpha(r: u8, g: u8, b: u8, a: u8) -> RGB {
+        let m = |c: u8| ((c as u32 * a as u32 + 0x7f) / 0xff) as u8;
+        RGB {
+            array: [m(b), m(g), m(r), a],
+        }
+    }
+
+    /// Premultiplied red.
+    pub fn r(self) -> u8 {
+        self.array[2]
+    }
+
+    /// Premultiplied green.
+    pub fn g(self) -> u8 {
+        self.array[1]
+    }
+
+    /// Premultiplied blue.
+    pub fn b(self) -> u8 {
+        self.array[0]
+    }
+
+    pub fn a(self) -> u8 {
+        self.array[3]
+    }
+
+    /// Returns non-premultiplied red, green, blue and alpha.
+    pub fn unpremultiplied(self) -> (u8, u8, u8, u8) {
+        let a = self.a() as u32;
+        let d = |c: u8| {
+            (c as u32 * 0xff + a / 2)
+                .checked_div(a)
+                .map_or(0, |c| c.min(0xff) as u8)
+        };
+        (d(self.r()), d(self.g()), d(self.b()), self.a())
+    }
+
+    /// Interpolates linearly towards `other`, which is reached at `t` = 1.
+    pub fn mix(self, other: RGB, t: f64) -> RGB {
+        let mut array = [0; 4];
+        for (c, (a, b)) in
+            array.iter_mut().zip(self.array.iter().zip(&other.array))
+        {
+            *c = (*a as f64 + (*b as f64 - *a as f64) * t).round() as u8;
+        }
+        RGB { array }
+    }
+
+    /// Scales all components, making the pixel `opacity` times as opaque.
+    pub fn multiply_alpha(self, opacity: f64) -> RGB {
+        let m = |c: u8| (c as f64 * opacity).round() as u8;
+        RGB {
+            array: [
+                m(self.array[0]),
+                m(self.array[1]),
+                m(self.array[2]),
+                m(self.array[3]),
+            ],
+        }
+    }
+
+    /// Composites `top` with the given opacity onto `self` using the
+    /// "over" operator.
+    pub fn over(self, top: RGB, opacity: f64) -> RGB {
+        let top = if opacity < 1.0 {
+            top.multiply_alpha(opacity)
+        } else {
+            top
+        };
+        let transmittance = 0xff - top.a() as u32;
+        let mix = |i: usize| {
+            top.array[i]
+                + ((self.array[i] as u32 * transmittance + 0x7f) / 0xff) as u8
+        };
+        RGB {
+            array: [mix(0), mix(1), mix(2), mix(3)],
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct SingleVecImage {
+    pub width: usize,
+    pub height: usize,
+    pub vector: Vec<u8>,
+}
+
+impl SingleVecImage {
+    pub fn new(
+        data: impl Iterator<Item = RGB>,
+        width: usize,
+        height: usize,
+    ) -> SingleVecImage {
+        SingleVecImage {
+            width,
+            height,
+            vector: data
+                .take(width * height)
+                .flat_map(|c| c.array.to_vec())
+                .collect(),
+        }
+    }
+
+    pub fn get(&self, x: usize, y: usize) -> RGB {
+        let i = 4 * (x + self.width * y);
+        let mut array = [0; 4];
+        array.clone_from_slice(&self.vector[i..i + 4]);
+        RGB { array }
+    }
+
+    pub fn set(&mut self, x: usize, y: usize, color: RGB) {
+        let i = 4 * (x + self.width * y);
+        self.vector[i..i + 4].clone_from_slice(&color.array);
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PenInput {
+    pub x: f64,
+    pub y: f64,
+    pub pressure: f64,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Rectangle {
+    pub x: f64,
+    pub y: f64,
+    pub width: f64,
+    pub height: f64,
+}
+
+impl Rectangle {
+    /// Returns the columns and rows of the pixels the rectangle touches
+    /// within an image of `size`.
+    pub fn pixels(&self, size: (usize, usize)) -> (Range<usize>, Range<usize>) {
+        let xs = (self.x.floor().max(0.0) as usize)
+            ..((self.x + self.width).ceil().max(0.0) as usize).min(size.0);
+        let ys = (self.y.floor().max(0.0) as usize)
+            ..((self.y + self.height).ceil().max(0.0) as usize).min(size.1);
+        (xs, ys)
+    }
+}
+
+/// Pixels added beyond a stroke when it grows an infinite canvas, so that
+/// the canvas does not grow at every step.
+const GROW_MARGIN: i64 = 256;
+
+/// A pixel touched by the current stroke. The stroke is blended onto the
+/// pixel as it was before the stroke, with the largest coverage so far, so
+/// that overlapping segments do not darken the edges.
+struct StrokePixel {
+    original: RGB,
+    coverage: f64,
+}
+
+pub type DrawHandler = Box<dyn Fn(&TiledImage, (usize, usize), Rectangle)>;
+
+pub struct Canvas {
+    pub drawer: DrawHandler,
+    viewport_size: (usize, usize),
+    pub canvas_size: (usize, usize),
+    /// The visible layers composited.
+    pub image: TiledImage,
+    layers: Vec<Layer>,
+    active_layer: usize,
+    next_layer_number: usize,
+    previous_input: Option<PenInput>,
+    pen_setting: PenSetting,
+    history: History,
+    stroke_pixels: HashMap<(usize, usize), StrokePixel>,
+    fill_setting: FillSetting,
+    /// Painting is limited to the selection if there is one.
+    selection: Option<Selection>,
+    transforming: Option<Transforming>,
+    /// Resampling of the pixels being transformed.
+    transform_filter: Filter,
+    /// Grows the canvas when drawing beyond its edges.
+    infinite: bool,
+    /// How far the origin moved since the last `take_growth`, if it moved.
+    growth: Option<(i64, i64)>,
+}
+
+/// Pixels of the active layer being transformed. The layer shows a preview
+/// until the transformation is applied.
+struct Transforming {
+    floating: Floating,
+    /// The layer before the pixels were lifted.
+    before: TiledImage,
+    /// The layer without the lifted pixels.
+    base: TiledImage,
+    /// Area the pixels were lifted from.
+    lifted: Rectangle,
+    /// Area covered by the preview.
+    shown: Option<Rectangle>,
+}
+
+fn copy_area(from: &TiledImage, to: &mut TiledImage, area: &Rectangle) {
+    let (x0, y0) = (area.x as usize, area.y as usize);
+    for y in y0..y0 + area.height as usize {
+        for x in x0..x0 + area.width as usize {
+            to.set(x, y, from.get(x, y));
+        }
+    }
+}
+
+impl Canvas {
+    pub fn new(drawer: DrawHandler, canvas_size: (usize, usize)) -> Canvas {
+        let background_color = RGB::new(0xff, 0xff, 0xff);
+        Canvas {
+            drawer,
+            viewport_size: canvas_size,
+            canvas_size,
+            image: TiledImage::new(
+                canvas_size.0,
+                canvas_size.1,
+                background_color,
+            ),
+            layers: vec![Layer::new(
+                "Background".to_string(),
+                canvas_size,
+                background_color,
+            )],
+            active_layer: 0,
+            next_layer_number: 1,
+            previous_input: None,
+            pen_setting: PenSetting {
+                size: 20.0,
+                opacity: 1.0,
+                color: RGB::new(0, 0, 0),
+                background_color,
+                antialias: true,
+                eraser: false,
+                pressure_curve: PressureCurve::LINEAR,
+            },
+            history: History::new(DEFAULT_MEMORY_BUDGET),
+            stroke_pixels: HashMap::new(),
+            fill_setting: FillSetting::default(),
+            selection: None,
+            transforming: None,
+            transform_filter: Filter::Bilinear,
+            infinite: false,
+            growth: None,
+        }
+    }
+
+    pub fn pen_stroke(&mut self, mut input: PenInput) {
+        if self.previous_input.is_none() {
+            self.apply_transform();
+        }
+        // A selection limits painting to the canvas anyway.
+        if self.infinite && self.selection.is_none() {
+            let (x0, y0, x1, y1) =
+                pen::bounds(&input, &self.previous_input, &self.pen_setting);
+            let (dx, dy) = self.grow_to(
+                x0.floor() as i64,
+                y0.floor() as i64,
+                x1.ceil() as i64,
+                y1.ceil() as i64,
+            );
+            input.x += dx as f64;
+            input.y += dy as f64;
+        }
+        let canvas_w = self.canvas_size.0 as i32;
+        let canvas_h = self.canvas_size.1 as i32;
+        let mut max_x = 0;
+        let mut min_x = canvas_w as u32;
+        let mut max_y = 0;
+        let mut min_y = canvas_h as u32;
+        let image = &mut self.layers[self.active_layer].image;
+        let stroke_pixels = &mut self.stroke_pixels;
+        let opacity = self.pen_setting.opacity;
+        let color = self.pen_setting.color.multiply_alpha(opacity);
+        let eraser = self.pen_setting.eraser;
+        let selection = &self.selection;
+        let pressure_opacity =
+            self.pen_setting.pressure_curve.opacity(input.pressure);
+        let changed_pixels =
+            pen::circle_pen(&input, &self.previous_input, &self.pen_setting)
+                .filter(|((x, y), _)| {
+                    0 <= *x && *x < canvas_w && 0 <= *y && *y < canvas_h
+                })
+                .filter(|((x, y), _)| {
+                    selection
+                        .as_ref()
+                        .is_none_or(|s| s.contains(*x as usize, *y as usize))
+                })
+                .map(|((x, y), coverage)| {
+                    ((x as u32, y as u32), coverage * pressure_opacity)
+                })
+                .inspect(|((x, y), coverage)| {
+                    let (x_u, y_u) = (*x as usize, *y as usize);
+                    let p =
+                        stroke_pixels.entry((x_u, y_u)).or_insert_with(|| {
+                            StrokePixel {
+                                original: image.get(x_u, y_u),
+                                coverage: 0.0,
+                            }
+                        });
+                    if *coverage > p.coverage {
+                        p.coverage = *coverage;
+                        let c = if eraser {
+                            p.original.multiply_alpha(1.0 - coverage * opacity)
+                        } else {
+                            p.original.over(color, *coverage)
+                        };
+                        image.set(x_u, y_u, c);
+                    }
+                    max_x = max_x.max(*x);
+                    min_x = min_x.min(*x);
+                    max_y = max_y.max(*y);
+                    min_y = min_y.min(*y);
+                })
+                .collect::<Vec<_>>();
+        self.previous_input = Some(input);
+        if !changed_pixels.is_empty() {
+            let changed_area = Rectangle {
+                x: min_x as f64,
+                y: min_y as f64,
+                width: (max_x - min_x + 1) as f64,
+                height: (max_y - min_y + 1) as f64,
+            };
+            self.draw(changed_area);
+        }
+    }
+
+    pub fn pen_stroke_end(&mut self) {
+        self.previous_input = None;
+        let image = &self.layers[self.active_layer].image;
+        let mut step = PixelStep::new(self.active_layer);
+        for ((x, y), p) in self.stroke_pixels.drain() {
+            step.push(x, y, p.original, image.get(x, y));
+        }
+        self.history.push(Step::Pixels(step));
+    }
+
+    /// Draws the points joined by the pen as one undoable stroke. Shapes are
+    /// always painted, leaving the eraser setting as it was.
+    pub fn draw_polyline(&mut self, points: &[(f64, f64)], pressure: f64) {
+        self.finish_editing();
+        let eraser = self.pen_setting.eraser;
+        self.set_eraser(false);
+        let start = self.growth.unwrap_or((0, 0));
+        for &(x, y) in points {
+            // Follow the origin if the stroke grew the canvas.
+            let (dx, dy) = self.growth.unwrap_or((0, 0));
+            self.pen_stroke(PenInput {
+                x: x + (dx - start.0) as f64,
+                y: y + (dy - start.1) as f64,
+                pressure,
+            });
+        }
+        self.pen_stroke_end();
+        self.set_eraser(eraser);
+    }
+
+    /// Grows the canvas with a margin so that it covers `x0..x1` and
+    /// `y0..y1`, as one undoable step. Returns how far the origin moved.
+    fn grow_to(&mut self, x0: i64, y0: i64, x1: i64, y1: i64) -> (i64, i64) {
+        let (width, height) =
+            (self.canvas_size.0 as i64, self.canvas_size.1 as i64);
+        // Growing to the left and up by whole tiles lets them be moved.
+        let tile = TILE_SIZE as i64;
+        let grow = |low: i64, high: i64, size: i64| {
+            (
+                if low < 0 {
+                    (low - GROW_MARGIN).div_euclid(tile) * tile
+                } else {
+                    0
+                },
+                if high > size {
+                    high + GROW_MARGIN
+                } else {
+                    size
+                },
+            )
+        };
+        let (left, right) = grow(x0, x1, width);
+        let (top, bottom) = grow(y0, y1, height);
+        if (left, top, right, bottom) == (0, 0, width, height) {
+            return (0, 0);
+        }
+        let step = GrowStep {
+            layer: self.active_layer,
+            before: self.canvas_size,
+            after: ((right - left) as usize, (bottom - top) as usize),
+            offset: (-left as usize, -top as usize),
+            background: self.pen_setting.background_color,
+        };
+        step.apply(&mut self.layers, false);
+        let (dx, dy) = step.offset;
+        self.stroke_pixels = self
+            .stroke_pixels
+            .drain()
+            .map(|((x, y), p)| ((x + dx, y + dy), p))
+            .collect();
+        if let Some(p) = &mut self.previous_input {
+            p.x += dx as f64;
+            p.y += dy as f64;
+        }
+        self.history.push(Step::Grow(step));
+        self.fit_to_layers();
+        self.moved_origin((-left, -top));
+        self.draw_all();
+        (-left, -top)
+    }
+
+    /// Records that the origin moved as the canvas grew or shrank.
+    fn moved_origin(&mut self, (dx, dy): (i64, i64)) {
+        if (dx, dy) == (0, 0) {
+            return;
+        }
+        let (x, y) = self.growth.unwrap_or((0, 0));
+        self.growth = Some((x + dx, y + dy));
+    }
+
+    /// Returns how far the origin moved since the last call, if it did.
+    pub fn take_growth(&mut self) -> Option<(i64, i64)> {
+        self.growth.take()
+    }
+
+    pub fn set_infinite(&mut self, infinite: bool) {
+        self.infinite = infinite;
+    }
+
+    /// Fills the area around (`x`, `y`) on the active layer with the pen
+    /// color.
+    pub fn fill(&mut self, x: usize, y: usize) {
+        if x >= self.canvas_size.0 || y >= self.canvas_size.1 {
+            return;
+        }
+        self.finish_editing();
+        let setting = &self.fill_setting;
+        let sample = if setting.sample_all_layers {
+            &self.image
+        } else {
+            &self.layers[self.active_layer].image
+        };
+        let mut mask = fill::fill_mask(sample, x, y, setting);
+        if let Some(selection) = &self.selection {
+            for (i, m) in mask.iter_mut().enumerate() {
+                *m = *m
+                    && selection.contains(i % sample.width, i / sample.width);
+            }
+        }
+        let color = self.pen_setting.color;
+        let opacity = self.pen_setting.opacity;
+        let image = &mut self.layers[self.active_layer].image;
+        let mut step = PixelStep::new(self.active_layer);
+        let (mut min_x, mut min_y) = (usize::MAX, usize::MAX);
+        let (mut max_x, mut max_y) = (0, 0);
+        if !mask.contains(&true) {
+            return;
+        }
+        for (i, _) in mask.iter().enumerate().filter(|(_, m)| **m) {
+            let (x, y) = (i % image.width, i / image.width);
+            let before = image.get(x, y);
+            let after = before.over(color, opacity);
+            image.set(x, y, after);
+            step.push(x, y, before, after);
+            min_x = min_x.min(x);
+            max_x = max_x.max(x);
+            min_y = min_y.min(y);
+            max_y = max_y.max(y);
+        }
+        self.history.push(Step::Pixels(step));
+        self.draw(Rectangle {
+            x: min_x as f64,
+            y: min_y as f64,
+            width: (max_x - min_x + 1) as f64,
+            height: (max_y - min_y + 1) as f64,
+        });
+    }
+
+    /// Returns the opaque color averaged over `size` x `size` pixels around
+    /// (`x`, `y`) of the merged image or of the active layer.
+    pub fn pick_color(
+        &self,
+        x: f64,
+        y: f64,
+        size: usize,
+        merged: bool,
+    ) -> Option<RGB> {
+        let image = if merged {
+            &self.image
+        } else {
+            &self.layers[self.active_layer].image
+        };
+        let c = image.average(
+            x.floor() as i64,
+            y.floor() as i64,
+            (size / 2) as i64,
+        )?;
+        if c.a() == 0 {
+            return None;
+        }
+        let (r, g, b, _) = c.unpremultiplied();
+        Some(RGB::new(r, g, b))
+    }
+
+    pub fn selection(&self) -> Option<&Selection> {
+        self.selection.as_ref()
+    }
+
+    /// Combines the pixels inside `polygon` with the selection.
+    pub fn select(&mut self, polygon: &[(f64, f64)], mode: SelectionMode) {
+        let (width, height) = self.canvas_size;
+        let new = Selection::from_polygon(polygon, width, height);
+        let selection = match (self.selection.take(), mode) {
+            (_, SelectionMode::Replace) | (None, SelectionMode::Add) => {
+                Some(new)
+            }
+            (None, _) => None,
+            (Some(mut s), _) => {
+                s.combine(&new, mode);
+                Some(s)
+            }
+        };
+        self.set_selection(selection);
+    }
+
+    pub fn select_all(&mut self) {
+        let (width, height) = self.canvas_size;
+        self.set_selection(Some(Selection::all(width, height)));
+    }
+
+    pub fn invert_selection(&mut self) {
+        let (width, height) = self.canvas_size;
+        let mut selection = self
+            .selection
+            .take()
+            .unwrap_or_else(|| Selection::from_polygon(&[], width, height));
+        selection.invert();
+        self.set_selection(Some(selection));
+    }
+
+    /// Sets the selection, dropping it if it is empty.
+    pub fn set_selection(&mut self, selection: Option<Selection>) {
+        self.finish_editing();
+        self.selection = selection.filter(|s| !s.is_empty());
+    }
+
+    /// Ends the stroke and applies the transformation in progress.
+    fn finish_editing(&mut self) {
+        self.pen_stroke_end();
+        self.apply_transform();
+    }
+
+    /// Lifts the selected pixels of the active layer, or the whole layer, to
+    /// transform them. The selection is dropped.
+    pub fn begin_transform(&mut self) {
+        if self.transforming.is_some() {
+            return;
+        }
+        self.pen_stroke_end();
+        let image = &mut self.layers[self.active_layer].image;
+        let before = image.clone();
+        let floating = match transform::lift(image, self.selection.as_ref()) {
+            Some(f) => f,
+            None => return,
+        };
+        let base = image.clone();
+        let shown = floating.stamp(image, self.transform_filter);
+        let lifted = Rectangle {
+            x: floating.origin.0 as f64,
+            y: floating.origin.1 as f64,
+            width: floating.image.width as f64,
+            height: floating.image.height as f64,
+        };
+        self.transforming = Some(Transforming {
+            floating,
+            before,
+            base,
+            lifted,
+            shown,
+        });
+        self.selection = None;
+    }
+
+    pub fn floating(&self) -> Option<&Floating> {
+        self.transforming.as_ref().map(|t| &t.floating)
+    }
+
+    /// Moves the floating pixels and updates the preview.
+    pub fn set_placement(&mut self, placement: Placement) {
+        let t = match &mut self.transforming {
+            Some(t) => t,
+            None => return,
+        };
+        let image = &mut self.layers[self.active_layer].image;
+        if let Some(shown) = &t.shown {
+            copy_area(&t.base, image, shown);
+        }
+        t.floating.placement = placement;
+        let shown = t.floating.stamp(image, self.transform_filter);
+        let area = match (t.shown, shown) {
+            (Some(a), Some(b)) => Some(transform::union(&a, &b)),
+            (a, b) => a.or(b),
+        };
+        t.shown = shown;
+        if let Some(area) = area {
+            self.draw(area);
+        }
+    }
+
+    pub fn transform_filter(&self) -> Filter {
+        self.transform_filter
+    }
+
+    /// Sets how the transformed pixels are resampled, updating the preview.
+    pub fn set_transform_filter(&mut self, filter: Filter) {
+        self.transform_filter = filter;
+        if let Some(t) = &self.transforming {
+            let placement = t.floating.placement;
+            self.set_placement(placement);
+        }
+    }
+
+    /// Puts the floating pixels back into the layer as one undoable step,
+    /// resampled the same way as the preview.
+    pub fn apply_transform(&mut self) {
+        let t = match self.transforming.take() {
+            Some(t) => t,
+            None => return,
+        };
+        let filter = self.transform_filter;
+        let image = &mut self.layers[self.active_layer].image;
+        if let Some(shown) = &t.shown {
+            copy_area(&t.base, image, shown);
+        }
+        let area = match t.floating.stamp(image, filter) {
+            Some(stamped) => transform::union(&t.lifted, &stamped),
+            None => t.lifted,
+        };
+        let mut step = PixelStep::new(self.active_layer);
+        let (x0, y0) = (area.x as usize, area.y as usize);
+        for y in y0..y0 + area.height as usize {
+            for x in x0..x0 + area.width as usize {
+                step.push(x, y, t.before.get(x, y), image.get(x, y));
+            }
+        }
+        self.history.push(Step::Pixels(step));
+        self.draw(match t.shown {
+            Some(shown) => transform::union(&area, &shown),
+            None => area,
+        });
+    }
+
+    /// Puts the floating pixels back where they were lifted from.
+    pub fn cancel_transform(&mut self) {
+        if let Some(t) = self.transforming.take() {
+            let area = match t.shown {
+                Some(shown) => transform::union(&t.lifted, &shown),
+                None => t.lifted,
+            };
+            self.layers[self.active_layer].image = t.before;
+            self.draw(area);
+        }
+    }
+
+    pub fn fill_setting(&self) -> FillSetting {
+        self.fill_setting
+    }
+
+    pub fn set_fill_setting(&mut self, setting: FillSetting) {
+        self.fill_setting = setting;
+    }
+
+    pub fn undo(&mut self) {
+        self.finish_editing();
+        if let Some((layer, area, shift)) = self.history.undo(&mut self.layers)
+        {
+            self.active_layer = layer.min(self.layers.len() - 1);
+            self.fit_to_layers();
+            self.moved_origin(shift);
+            self.draw(area);
+        }
+    }
+
+    pub fn redo(&mut self) {
+        self.finish_editing();
+        if let Some((layer, area, shift)) = self.history.redo(&mut self.layers)
+        {
+            self.active_layer = layer.min(self.layers.len() - 1);
+            self.fit_to_layers();
+            self.moved_origin(shift);
+            self.draw(area);
+        }
+    }
+
+    pub fn set_history_memory_budget(&mut self, memory_budget: usize) {
+        self.history.set_memory_budget(memory_budget);
+    }
+
+    /// Recomposites `area` from the layers and passes it to the drawer.
+    fn draw(&mut self, mut area: Rectangle) {
+        // Blank tiles show the composited fill, so all of them change with
+        // it, as when the background is hidden.
+        let fill = layer::composite_fill(&self.layers);
+        if fill != self.image.fill() {
+            let (width, height) = self.canvas_size;
+            self.image = TiledImage::new(width, height, fill);
+            area = Rectangle {
+                x: 0.0,
+                y: 0.0,
+                width: width as f64,
+                height: height as f64,
+            };
+        }
+        let x_range = (area.x.max(0.0) as usize)
+            ..((area.x + area.width).max(0.0) as usize).min(self.canvas_size.0);
+        let y_range = (area.y.max(0.0) as usize)
+            ..((area.y + area.height).max(0.0) as usize)
+                .min(self.canvas_size.1);
+        for ty in (y_range.start / TILE_SIZE)..y_range.end.div_ceil(TILE_SIZE) {
+            for tx in
+                (x_range.start / TILE_SIZE)..x_range.end.div_ceil(TILE_SIZE)
+            {
+                let (x0, y0) = (tx * TILE_SIZE, ty * TILE_SIZE);
+                // Tiles no layer has painted on are left blank.
+                if self
+                    .layers
+                    .iter()
+                    .all(|l| !l.visible || l.image.is_blank(x0, y0))
+                {
+                    self.image.clear_tile(x0, y0);
+                    continue;
+                }
+                for y in
+                    y0.max(y_range.start)..(y0 + TILE_SIZE).min(y_range.end)
+                {
+                    for x in
+                        x0.max(x_range.start)..(x0 + TILE_SIZE).min(x_range.end)
+                    {
+                        self.image.set(
+                            x,
+                            y,
+                            layer::composite(&self.layers, x, y),
+                        );
+                    }
+                }
+            }
+        }
+        (self.drawer)(&self.image, self.viewport_size, area);
+    }
+
+    fn draw_all(&mut self) {
+        self.draw(Rectangle {
+            x: 0.0,
+            y: 0.0,
+            width: self.canvas_size.0 as f64,
+            height: self.canvas_size.1 as f64,
+        });
+    }
+
+    pub fn reflect_all(&mut self) {
+        (self.drawer)(
+            &self.image,
+            self.viewport_size,
+            Rectangle {
+                x: 0.0,
+                y: 0.0,
+                width: self.viewport_size.0 as f64,
+                height: self.viewport_size.1 as f64,
+            },
+        );
+    }
+
+    /// Replaces the layers with ones of a different size as one undoable
+    /// step.
+    fn replace_layers(&mut self, layers: Vec<Layer>) {
+        self.finish_editing();
+        let before = std::mem::replace(&mut self.layers, layers.clone());
+        self.history.push(Step::ReplaceLayers {
+            before,
+            after: layers,
+        });
+        self.fit_to_layers();
+        self.draw_all();
+    }
+
+    /// Updates the canvas size after the layers changed their size. The
+    /// selection is dropped in that case.
+    fn fit_to_layers(&mut self) {
+        let image = &self.layers[0].image;
+        let size = (image.width, image.height);
+        if size != self.canvas_size {
+            self.canvas_size = size;
+            self.viewport_size = size;
+            self.image = TiledImage::new(
+                size.0,
+                size.1,
+                layer::composite_fill(&self.layers),
+            );
+            self.selection = None;
+        }
+    }
+
+    /// Starts over with a single layer of `size` filled with `background`.
+    pub fn new_image(&mut self, size: (usize, usize), background: RGB) {
+        self.set_image(TiledImage::new(size.0, size.1, background));
+    }
+
+    /// Changes the canvas size without scaling the layers. `anchor` tells
+    /// where the old canvas is put, from (0, 0) for the top left to (1, 1)
+    /// for the bottom right. The new area of the bottom layer is filled with
+    /// the background color and that of the other layers is transparent.
+    pub fn resize_canvas(&mut self, size: (usize, usize), anchor: (f64, f64)) {
+        if size == self.canvas_size {
+            return;
+        }
+        let offset = |new: usize, old: usize, anchor: f64| {
+            ((new as f64 - old as f64) * anchor).round() as i64
+        };
+        let offset = (
+            offset(size.0, self.canvas_size.0, anchor.0),
+            offset(size.1, self.canvas_size.1, anchor.1),
+        );
+        self.resize_layers(size, offset);
+    }
+
+    /// Cuts the canvas down to `area`.
+    pub fn crop(&mut self, area: Rectangle) {
+        let size = (area.width as usize, area.height as usize);
+        if size == self.canvas_size || size.0 == 0 || size.1 == 0 {
+            return;
+        }
+        self.resize_layers(size, (-area.x as i64, -area.y as i64));
+    }
+
+    /// Resizes all layers to `size`, putting their pixels at `offset`.
+    fn resize_layers(&mut self, size: (usize, usize), offset: (i64, i64)) {
+        let mut layers = self.layers.clone();
+        for (i, l) in layers.iter_mut().enumerate() {
+            let background = if i == 0 {
+                self.pen_setting.background_color
+            } else {
+                RGB::TRANSPARENT
+            };
+            l.image.resize(size.0, size.1, offset, background);
+        }
+        self.replace_layers(layers);
+    }
+
+    /// Scales all layers to `size`.
+    pub fn scale_image(&mut self, size: (usize, usize), method: Resample) {
+        if size == self.canvas_size {
+            return;
+        }
+        let layers = self
+            .layers
+            .iter()
+            .map(|l| Layer {
+                image: resample::scale(&l.image, size.0, size.1, method),
+                ..l.clone()
+            })
+            .collect();
+        self.replace_layers(layers);
+    }
+
+    /// Replaces all layers with `image`, e.g. an opened file. The history is
+    /// cleared because it refers to the previous layers.
+    pub fn set_image(&mut self, image: TiledImage) {
+        self.canvas_size = (image.width, image.height);
+        self.viewport_size = self.canvas_size;
+        self.image = image.clone();
+        self.layers = vec![Layer {
+            name: "Background".to_string(),
+            image,
+            visible: true,
+            opacity: 1.0,
+        }];
+        self.active_layer = 0;
+        self.next_layer_number = 1;
+        self.previous_input = None;
+        self.stroke_pixels.clear();
+        self.history.clear();
+        self.selection = None;
+        self.transforming = None;
+    }
+
+    pub fn get_size(&self) -> (usize, usize) {
+        self.canvas_size
+    }
+
+    pub fn set_pen_size(&mut self, size: f64) {
+        self.pen_setting.size = size;
+    }
+
+    pub fn set_pen_opacity(&mut self, opacity: f64) {
+        self.pen_setting.opacity = opacity;
+    }
+
+    /// Switches between painting and erasing. A stroke in progress is ended
+    /// when the mode changes.
+    pub fn set_eraser(&mut self, eraser: bool) {
+        if self.pen_setting.eraser != eraser {
+            self.pen_stroke_end();
+            self.pen_setting.eraser = eraser;
+        }
+    }
+
+    pub fn set_pen_antialias(&mut self, antialias: bool) {
+        self.pen_setting.antialias = antialias;
+    }
+
+    pub fn pressure_curve(&self) -> PressureCurve {
+        self.pen_setting.pressure_curve
+    }
+
+    pub fn set_pressure_curve(&mut self, curve: PressureCurve) {
+        self.pen_setting.pressure_curve = curve;
+    }
+
+    /// Returns the foreground and background colors.
+    pub fn pen_colors(&self) -> (RGB, RGB) {
+        (self.pen_setting.color, self.pen_setting.background_color)
+    }
+
+    pub fn set_pen_colors(&mut self, foreground: RGB, background: RGB) {
+        self.pen_setting.color = foreground;
+        self.pen_setting.background_color = background;
+    }
+
+    pub fn swap_pen_colors(&mut self) {
+        let s = &mut self.pen_setting;
+        std::mem::swap(&mut s.color, &mut s.background_color);
+    }
+
+    pub fn layers(&self) -> &[Layer] {
+        &self.layers
+    }
+
+    pub fn active_layer(&self) -> usize {
+        self.active_layer
+    }
+
+    pub fn set_active_layer(&mut self, index: usize) {
+        if index < self.layers.len() && index != self.active_layer {
+            self.finish_editing();
+            self.active_layer = index;
+        }
+    }
+
+    fn insert_layer(&mut self, index: usize, layer: Layer) {
+        self.finish_editing();
+        self.layers.insert(index, layer.clone());
+        self.history.push(Step::InsertLayer { index, layer });
+        self.active_layer = index;
+        self.draw_all();
+    }
+
+    /// Adds an empty layer above the active layer.
+    pub fn add_layer(&mut self) {
+        let name = format!("Layer {}", self.next_layer_number);
+        self.next_layer_number += 1;
+        let layer = Layer::new(name, self.canvas_size, RGB::TRANSPARENT);
+        self.insert_layer(self.active_layer + 1, layer);
+    }
+
+    pub fn duplicate_layer(&mut self) {
+        self.apply_transform();
+        let mut layer = self.layers[self.active_layer].clone();
+        layer.name = format!("{} copy", layer.name);
+        self.insert_layer(self.active_layer + 1, layer);
+    }
+
+    /// Deletes the active layer unless it is the last one.
+    pub fn delete_layer(&mut self) {
+        if self.layers.len() <= 1 {
+            return;
+        }
+        self.finish_editing();
+        let index = self.active_layer;
+        let layer = self.layers.remove(index);
+        self.history.push(Step::RemoveLayer { index, layer });
+        self.active_layer = index.saturating_sub(1);
+        self.draw_all();
+    }
+
+    /// Moves the active layer one step up (towards the top) or down.
+    pub fn move_layer(&mut self, up: bool) {
+        let from = self.active_layer;
+        let to = if up {
+            from + 1
+        } else {
+            match from.checked_sub(1) {
+                Some(to) => to,
+                None => return,
+            }
+        };
+        if to >= self.layers.len() {
+            return;
+        }
+        self.finish_editing();
+        self.layers.swap(from, to);
+        self.history.push(Step::MoveLayer { from, to });
+        self.active_layer = to;
+        self.draw_all();
+    }
+
+    /// Shows or hides the layer at `index`, if there is one.
+    pub fn set_layer_visible(&mut self, index: usize, visible: bool) {
+        if let Some(layer) = self.layers.get(index) {
+            self.set_layer_properties(index, (visible, layer.opacity));
+        }
+    }
+
+    /// Sets the opacity of the layer at `index`, if there is one.
+    pub fn set_layer_opacity(&mut self, index: usize, opacity: f64) {
+        if let Some(layer) = self.layers.get(index) {
+            self.set_layer_properties(index, (layer.visible, opacity));
+        }
+    }
+
+    fn set_layer_properties(&mut self, index: usize, after: (bool, f64)) {
+        let layer = &mut self.layers[index];
+        let before = (layer.visible, layer.opacity);
+        layer.visible = after.0;
+        layer.opacity = after.1;
+        self.history.push(Step::LayerProperties {
+            index,
+            before,
+            after,
+        });
+        self.draw_all();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{cell::RefCell, rc::Rc, time};
+
+    /// Time taken by `segments` pen strokes of `pen_size` on a `size` x
+    /// `size` canvas, each changed area being copied to a buffer kept like
+    /// the display surface, the way the viewport uploads it.
+    fn stroke_time(
+        size: usize,
+        pen_size: f64,
+        segments: usize,
+    ) -> time::Duration {
+        let buffer = Rc::new(RefCell::new(vec![0; 4 * size * size]));
+        let drawer: DrawHandler = Box::new(move |image, canvas_size, area| {
+            let (xs, ys) = area.pixels(canvas_size);
+            image.copy_to(xs, ys, &mut buffer.borrow_mut(), 4 * canvas_size.0);
+        });
+        let mut canvas = Canvas::new(drawer, (size, size));
+        canvas.set_pen_size(pen_size);
+        let start = time::Instant::now();
+        for i in 0..segments {
+            canvas.pen_stroke(PenInput {
+                x: 10.0 + (i % 200) as f64,
+                y: 10.0 + (i / 200) as f64,
+                pressure: 1.0,
+            });
+        }
+        canvas.pen_stroke_end();
+        start.elapsed()
+    }
+
+    /// Checks that a stroke segment costs about the same however large the
+    /// canvas is, and more the larger the area it changes. Run with
+    /// `cargo test --release -- --ignored`.
+    #[test]
+    #[ignore]
+    fn bench_stroke_segment() {
+        let small = stroke_time(256, 20.0, 2000);
+        let large = stroke_time(8192, 20.0, 2000);
+        assert!(
+            large < small * 3,
+            "256 x 256: {:?}, 8192 x 8192: {:?}",
+            small,
+            large
+        );
+        let thin = stroke_time(1024, 4.0, 2000);
+        let thick = stroke_time(1024, 64.0, 2000);
+        assert!(
+            thick > thin * 10,
+            "size 4: {:?}, size 64: {:?}",
+            thin,
+            thick
+        );
+    }
+
+    #[test]
+    fn test_infinite_canvas() {
+        let mut canvas = Canvas::new(Box::new(|_, _, _| ()), (4, 4));
+        canvas.set_infinite(true);
+        canvas.pen_stroke(PenInput {
+            x: -2.0,
+            y: 2.0,
+            pressure: 1.0,
+        });
+        canvas.pen_stroke_end();
+        let (dx, dy) = canvas.take_growth().unwrap();
+        assert!(dx > GROW_MARGIN && dy > GROW_MARGIN);
+        let (width, height) = canvas.get_size();
+        assert!(width as i64 > dx + 4 && height as i64 > dy + 4);
+        assert_ne!(
+            canvas.image.get((dx - 2) as usize, (dy + 2) as usize).a(),
+            0
+        );
+        assert_eq!(canvas.take_growth(), None);
+        canvas.undo();
+        assert_eq!(canvas.take_growth(), None);
+        canvas.undo();
+        assert_eq!(canvas.get_size(), (4, 4));
+        assert_eq!(canvas.take_growth(), Some((-dx, -dy)));
+    }
+
+    #[test]
+    fn test_background_change_keeps_tiles_blank() {
+        let mut canvas = Canvas::new(Box::new(|_, _, _| ()), (4096, 4096));
+        canvas.set_layer_visible(0, false);
+        assert_eq!(canvas.image.memory_size(), 0);
+        assert_eq!(canvas.image.get(100, 100).a(), 0);
+        canvas.set_layer_visible(0, true);
+        canvas.set_layer_opacity(0, 0.5);
+        assert_eq!(canvas.image.memory_size(), 0);
+        assert_eq!(canvas.image.get(100, 100).a(), 0x80);
+    }
+
+    #[test]
+    fn test_layer_properties() {
+        let mut canvas = Canvas::new(Box::new(|_, _, _| ()), (4, 4));
+        canvas.set_layer_visible(1, false);
+        canvas.set_layer_opacity(1, 0.5);
+        canvas.set_layer_visible(0, false);
+        assert_eq!(canvas.image.get(0, 0), RGB::TRANSPARENT);
+        canvas.undo();
+        assert_eq!(canvas.image.get(0, 0), RGB::new(0xff, 0xff, 0xff));
+    }
+
+    #[test]
+    fn test_polyline_keeps_eraser() {
+        let mut canvas = Canvas::new(Box::new(|_, _, _| ()), (10, 10));
+        canvas.set_eraser(true);
+        canvas.draw_polyline(&[(1.0, 5.0), (9.0, 5.0)], 1.0);
+        assert!(canvas.pen_setting.eraser);
+        assert_eq!(canvas.image.get(5, 5), RGB::new(0, 0, 0));
+    }
+
+    #[test]
+    fn test_crop() {
+        let mut canvas = Canvas::new(Box::new(|_, _, _| ()), (4, 3));
+        canvas.layers[0].image.set(2, 1, RGB::new(0, 0, 0));
+        canvas.crop(Rectangle {
+            x: 2.0,
+            y: 1.0,
+            width: 2.0,
+            height: 2.0,
+        });
+        assert_eq!(canvas.get_size(), (2, 2));
+        assert_eq!(canvas.image.get(0, 0), RGB::new(0, 0, 0));
+        canvas.undo();
+        assert_eq!(canvas.get_size(), (4, 3));
+        assert_eq!(canvas.image.get(2, 1), RGB::new(0, 0, 0));
+    }
+
+    #[test]
+    fn test_transform_filter() {
+        let placement = Placement {
+            angle: 0.3,
+            ..Placement::IDENTITY
+        };
+        for &filter in &[Filter::Nearest, Filter::Bilinear] {
+            let mut canvas = Canvas::new(Box::new(|_, _, _| ()), (16, 16));
+            for y in 4..12 {
+                for x in 4..12 {
+                    canvas.layers[0].image.set(x, y, RGB::new(0, 0, 0));
+                }
+            }
+            canvas.set_transform_filter(filter);
+            canvas.begin_transform();
+            canvas.set_placement(placement);
+            let preview = canvas.image.to_image().vector;
+            canvas.apply_transform();
+            assert_eq!(canvas.image.to_image().vector, preview);
+            let blended = preview
+                .chunks(4)
+                .any(|p| p.iter().any(|&c| c != 0 && c != 0xff));
+            assert_eq!(blended, filter == Filter::Bilinear);
+        }
+    }
+}
@@ -0,0 +1,208 @@
+use std::collections::VecDeque;
+
+use super::*;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct FillSetting {
+    /// Largest difference of a channel from the clicked color, from 0 to 1.
+    pub tolerance: f64,
+    /// Fills only the area connected to the clicked pixel.
+    pub contiguous: bool,
+    /// Compares the colors of all visible layers instead of the active one.
+    pub sample_all_layers: bool,
+    /// Pixels by which the filled area is grown, to cover the anti-aliased
+    /// edges of lines.
+    pub expand: usize,
+    /// Gaps in the outline up to this many pixels wide are treated as
+    /// closed.
+    pub close_gaps: usize,
+}
+
+impl Default for FillSetting {
+    fn default() -> FillSetting {
+        FillSetting {
+            tolerance: 0.1,
+            contiguous: true,
+            sample_all_layers: false,
+            expand: 1,
+            close_gaps: 0,
+        }
+    }
+}
+
+fn similar(a: RGB, b: RGB, tolerance: f64) -> bool {
+    let limit = (tolerance * 0xff as f64).round() as i32;
+    [
+        (a.r(), b.r()),
+        (a.g(), b.g()),
+        (a.b(), b.b()),
+        (a.a(), b.a()),
+    ]
+    .iter()
+    .all(|&(a, b)| (a as i32 - b as i32).abs() <= limit)
+}
+
+/// Grows the set pixels of `mask` by `r` pixels in each direction, as a
+/// square.
+fn dilate(mask: &[bool], width: usize, height: usize, r: usize) -> Vec<bool> {
+    if r == 0 {
+        return mask.to_vec();
+    }
+    // Sliding window along one axis; `get` maps (line, i) to an index.
+    let pass = |src: &[bool],
+                lines: usize,
+                len: usize,
+                get: &dyn Fn(usize, usize) -> usize| {
+        let mut dst = vec![false; src.len()];
+        for line in 0..lines {
+            let mut count = 0;
+            for i in 0..(len + r) {
+                if i < len && src[get(line, i)] {
+                    count += 1;
+                }
+                if i > 2 * r && src[get(line, i - 2 * r - 1)] {
+                    count -= 1;
+                }
+                if i >= r {
+                    dst[get(line, i - r)] = count > 0;
+                }
+            }
+        }
+        dst
+    };
+    let rows = pass(mask, height, width, &|y, x| y * width + x);
+    pass(&rows, width, height, &|x, y| y * width + x)
+}
+
+/// Marks the pixels reachable from (`x`, `y`) through `passable` pixels.
+fn flood(
+    passable: &[bool],
+    width: usize,
+    height: usize,
+    x: usize,
+    y: usize,
+) -> Vec<bool> {
+    let mut mask = vec![false; passable.len()];
+    let mut queue = VecDeque::new();
+    mask[y * width + x] = true;
+    queue.push_back((x, y));
+    while let Some((x, y)) = queue.pop_front() {
+        let neighbors = [
+            (x.wrapping_sub(1), y),
+            (x + 1, y),
+            (x, y.wrapping_sub(1)),
+            (x, y + 1),
+        ];
+        for &(nx, ny) in &neighbors {
+            if nx < width && ny < height {
+                let i = ny * width + nx;
+                if passable[i] && !mask[i] {
+                    mask[i] = true;
+                    queue.push_back((nx, ny));
+                }
+            }
+        }
+    }
+    mask
+}
+
+/// Returns the pixels to fill when clicking (`x`, `y`) of `image`.
+pub fn fill_mask(
+    image: &TiledImage,
+    x: usize,
+    y: usize,
+    setting: &FillSetting,
+) -> Vec<bool> {
+    let (width, height) = (image.width, image.height);
+    let target = image.get(x, y);
+    let inside = (0..width * height)
+        .map(|i| {
+            similar(image.get(i % width, i / width), target, setting.tolerance)
+        })
+        .collect::<Vec<_>>();
+    let mask = if !setting.contiguous {
+        inside.clone()
+    } else {
+        let r = setting.close_gaps.div_ceil(2);
+        let walls = inside.iter().map(|i| !i).collect::<Vec<_>>();
+        let narrowed = dilate(&walls, width, height, r)
+            .into_iter()
+            .map(|w| !w)
+            .collect::<Vec<_>>();
+        if r == 0 || !narrowed[y * width + x] {
+            flood(&inside, width, height, x, y)
+        } else {
+            // Fill with the gaps closed, then grow back to the outline.
+            let core = flood(&narrowed, width, height, x, y);
+            dilate(&core, width, height, r)
+                .into_iter()
+                .zip(&inside)
+                .map(|(m, i)| m && *i)
+                .collect()
+        }
+    };
+    dilate(&mask, width, height, setting.expand)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const WHITE: RGB = RGB::new(0xff, 0xff, 0xff);
+    const BLACK: RGB = RGB::new(0, 0, 0);
+
+    /// A 9x9 white image with a black box outline from 2 to 6, with a gap
+    /// of `gap` pixels on the right side.
+    fn boxed(gap: usize) -> TiledImage {
+        let mut image = TiledImage::new(9, 9, WHITE);
+        for i in 2..=6 {
+            image.set(i, 2, BLACK);
+            image.set(i, 6, BLACK);
+            image.set(2, i, BLACK);
+            if i < 4 || 4 + gap <= i {
+                image.set(6, i, BLACK);
+            }
+        }
+        image
+    }
+
+    fn count(mask: &[bool]) -> usize {
+        mask.iter().filter(|m| **m).count()
+    }
+
+    #[test]
+    fn test_fill_contiguous() {
+        let setting = FillSetting {
+            expand: 0,
+            ..FillSetting::default()
+        };
+        assert_eq!(count(&fill_mask(&boxed(0), 4, 4, &setting)), 9);
+        assert_eq!(count(&fill_mask(&boxed(1), 4, 4, &setting)), 81 - 15);
+        let global = FillSetting {
+            contiguous: false,
+            ..setting
+        };
+        assert_eq!(count(&fill_mask(&boxed(0), 4, 4, &global)), 81 - 16);
+    }
+
+    #[test]
+    fn test_fill_close_gaps() {
+        let setting = FillSetting {
+            expand: 0,
+            close_gaps: 1,
+            ..FillSetting::default()
+        };
+        let mask = fill_mask(&boxed(1), 4, 4, &setting);
+        assert!(mask[4 * 9 + 4]);
+        assert!(!mask[0]);
+    }
+
+    #[test]
+    fn test_dilate() {
+        let mut mask = vec![false; 25];
+        mask[12] = true;
+        let dilated = dilate(&mask, 5, 5, 1);
+        assert_eq!(count(&dilated), 9);
+        assert!(dilated[6] && dilated[18] && !dilated[0]);
+    }
+}
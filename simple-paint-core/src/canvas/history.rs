@@ -0,0 +1,488 @@
+use std::collections::VecDeque;
+use std::mem::size_of;
+
+use super::*;
+
+pub const DEFAULT_MEMORY_BUDGET: usize = 256 * 1024 * 1024;
+
+#[derive(Debug, Clone, Copy)]
+struct PixelChange {
+    x: usize,
+    y: usize,
+    before: RGB,
+    after: RGB,
+}
+
+/// Pixels of one layer changed by a single operation, e.g. a pen stroke.
+#[derive(Debug)]
+pub struct PixelStep {
+    layer: usize,
+    changes: Vec<PixelChange>,
+}
+
+impl PixelStep {
+    pub fn new(layer: usize) -> PixelStep {
+        PixelStep {
+            layer,
+            changes: Vec::new(),
+        }
+    }
+
+    pub fn push(&mut self, x: usize, y: usize, before: RGB, after: RGB) {
+        if before != after {
+            self.changes.push(PixelChange {
+                x,
+                y,
+                before,
+                after,
+            });
+        }
+    }
+
+    fn apply(
+        &self,
+        image: &mut TiledImage,
+        color: impl Fn(&PixelChange) -> RGB,
+    ) -> Rectangle {
+        let mut max_x = 0;
+        let mut min_x = usize::MAX;
+        let mut max_y = 0;
+        let mut min_y = usize::MAX;
+        for c in &self.changes {
+            image.set(c.x, c.y, color(c));
+            max_x = max_x.max(c.x);
+            min_x = min_x.min(c.x);
+            max_y = max_y.max(c.y);
+            min_y = min_y.min(c.y);
+        }
+        Rectangle {
+            x: min_x as f64,
+            y: min_y as f64,
+            width: (max_x - min_x + 1) as f64,
+            height: (max_y - min_y + 1) as f64,
+        }
+    }
+}
+
+/// Growth of all layers, e.g. when drawing beyond the edge of an infinite
+/// canvas. The new area of the bottom layer is filled with `background` and
+/// that of the others is transparent.
+#[derive(Debug)]
+pub struct GrowStep {
+    pub layer: usize,
+    pub before: (usize, usize),
+    pub after: (usize, usize),
+    /// Where the old pixels are put in the grown layers.
+    pub offset: (usize, usize),
+    pub background: RGB,
+}
+
+impl GrowStep {
+    /// Grows the layers, or shrinks them back if `backward` is set.
+    pub fn apply(&self, layers: &mut [Layer], backward: bool) {
+        let (ox, oy) = (self.offset.0 as i64, self.offset.1 as i64);
+        let ((width, height), offset) = if backward {
+            (self.before, (-ox, -oy))
+        } else {
+            (self.after, (ox, oy))
+        };
+        for (i, l) in layers.iter_mut().enumerate() {
+            let background = if i == 0 {
+                self.background
+            } else {
+                RGB::TRANSPARENT
+            };
+            l.image.resize(width, height, offset, background);
+        }
+    }
+
+    /// How far the origin of the canvas moves.
+    fn shift(&self, backward: bool) -> (i64, i64) {
+        let (x, y) = (self.offset.0 as i64, self.offset.1 as i64);
+        if backward {
+            (-x, -y)
+        } else {
+            (x, y)
+        }
+    }
+}
+
+/// One undoable operation.
+#[derive(Debug)]
+pub enum Step {
+    Pixels(PixelStep),
+    InsertLayer {
+        index: usize,
+        layer: Layer,
+    },
+    RemoveLayer {
+        index: usize,
+        layer: Layer,
+    },
+    MoveLayer {
+        from: usize,
+        to: usize,
+    },
+    /// The visibility and opacity of a layer changed.
+    LayerProperties {
+        index: usize,
+        before: (bool, f64),
+        after: (bool, f64),
+    },
+    /// All layers replaced, e.g. when the canvas is resized.
+    ReplaceLayers {
+        before: Vec<Layer>,
+        after: Vec<Layer>,
+    },
+    Grow(GrowStep),
+}
+
+fn whole_area(layers: &[Layer]) -> Rectangle {
+    let image = &layers[0].image;
+    Rectangle {
+        x: 0.0,
+        y: 0.0,
+        width: image.width as f64,
+        height: image.height as f64,
+    }
+}
+
+fn insert_or_remove(
+    layers: &mut Vec<Layer>,
+    index: usize,
+    layer: &Layer,
+    insert: bool,
+) -> (usize, Rectangle) {
+    if insert {
+        layers.insert(index, layer.clone());
+        (index, whole_area(layers))
+    } else {
+        layers.remove(index);
+        (index.saturating_sub(1), whole_area(layers))
+    }
+}
+
+impl Step {
+    fn is_empty(&self) -> bool {
+        match self {
+            Step::Pixels(p) => p.changes.is_empty(),
+            Step::MoveLayer { from, to } => from == to,
+            Step::LayerProperties { before, after, .. } => before == after,
+            _ => false,
+        }
+    }
+
+    /// Bytes the step keeps alive. Tiles shared with the layers, such as
+    /// those a resize only moved, are not counted.
+    fn memory_size(&self) -> usize {
+        match self {
+            Step::Pixels(p) => p.changes.len() * size_of::<PixelChange>(),
+            Step::InsertLayer { layer, .. }
+            | Step::RemoveLayer { layer, .. } => {
+                layer.image.unshared_memory_size()
+            }
+            Step::MoveLayer { .. }
+            | Step::LayerProperties { .. }
+            | Step::Grow(_) => 0,
+            Step::ReplaceLayers { before, after } => before
+                .iter()
+                .chain(after)
+                .map(|l| l.image.unshared_memory_size())
+                .sum(),
+        }
+    }
+
+    /// How far applying or reverting the step moves the origin of the
+    /// canvas.
+    fn shift(&self, backward: bool) -> (i64, i64) {
+        match self {
+            Step::Grow(g) => g.shift(backward),
+            _ => (0, 0),
+        }
+    }
+
+    /// Applies the step to `layers`, or reverts it if `backward` is set.
+    /// Returns the index of the affected layer and the changed area.
+    fn apply(
+        &self,
+        layers: &mut Vec<Layer>,
+        backward: bool,
+    ) -> (usize, Rectangle) {
+        match self {
+            Step::Pixels(p) => {
+                let image = &mut layers[p.layer].image;
+                let area = if backward {
+                    p.apply(image, |c| c.before)
+                } else {
+                    p.apply(image, |c| c.after)
+                };
+                (p.layer, area)
+            }
+            Step::InsertLayer { index, layer } => {
+                insert_or_remove(layers, *index, layer, !backward)
+            }
+            Step::RemoveLayer { index, layer } => {
+                insert_or_remove(layers, *index, layer, backward)
+            }
+            Step::MoveLayer { from, to } => {
+                let (from, to) =
+                    if backward { (*to, *from) } else { (*from, *to) };
+                let layer = layers.remove(from);
+                layers.insert(to, layer);
+                (to, whole_area(layers))
+            }
+            Step::LayerProperties {
+                index,
+                before,
+                after,
+            } => {
+                let layer = &mut layers[*index];
+                let (visible, opacity) = if backward { before } else { after };
+                layer.visible = *visible;
+                layer.opacity = *opacity;
+                (*index, whole_area(layers))
+            }
+            Step::ReplaceLayers { before, after } => {
+                *layers = if backward { before } else { after }.clone();
+                (0, whole_area(layers))
+            }
+            Step::Grow(g) => {
+                g.apply(layers, backward);
+                (g.layer, whole_area(layers))
+            }
+        }
+    }
+}
+
+/// Undo and redo stacks. The oldest steps are discarded once the stored
+/// steps use more than `memory_budget` bytes.
+pub struct History {
+    /// Steps with their memory size, measured when they were pushed.
+    undo_stack: VecDeque<(Step, usize)>,
+    redo_stack: Vec<(Step, usize)>,
+    memory_budget: usize,
+    memory_usage: usize,
+}
+
+impl History {
+    pub fn new(memory_budget: usize) -> History {
+        History {
+            undo_stack: VecDeque::new(),
+            redo_stack: Vec::new(),
+            memory_budget,
+            memory_usage: 0,
+        }
+    }
+
+    pub fn push(&mut self, step: Step) {
+        if step.is_empty() {
+            return;
+        }
+        for (_, size) in self.redo_stack.drain(..) {
+            self.memory_usage -= size;
+        }
+        // Dragging the opacity slider makes a single step.
+        if let (
+            Step::LayerProperties { index, after, .. },
+            Some((
+                Step::LayerProperties {
+                    index: i, after: a, ..
+                },
+                _,
+            )),
+        ) = (&step, self.undo_stack.back_mut())
+        {
+            if index == i {
+                *a = *after;
+                return;
+            }
+        }
+        let size = step.memory_size();
+        self.memory_usage += size;
+        self.undo_stack.push_back((step, size));
+        self.shrink();
+    }
+
+    /// Reverts the last step. Returns the index of the affected layer, the
+    /// changed area and how far the origin of the canvas moved.
+    pub fn undo(
+        &mut self,
+        layers: &mut Vec<Layer>,
+    ) -> Option<(usize, Rectangle, (i64, i64))> {
+        let (step, size) = self.undo_stack.pop_back()?;
+        let (layer, area) = step.apply(layers, true);
+        let shift = step.shift(true);
+        self.redo_stack.push((step, size));
+        Some((layer, area, shift))
+    }
+
+    pub fn redo(
+        &mut self,
+        layers: &mut Vec<Layer>,
+    ) -> Option<(usize, Rectangle, (i64, i64))> {
+        let (step, size) = self.redo_stack.pop()?;
+        let (layer, area) = step.apply(layers, false);
+        let shift = step.shift(false);
+        self.undo_stack.push_back((step, size));
+        Some((layer, area, shift))
+    }
+
+    pub fn clear(&mut self) {
+        self.undo_stack.clear();
+        self.redo_stack.clear();
+        self.memory_usage = 0;
+    }
+
+    pub fn set_memory_budget(&mut self, memory_budget: usize) {
+        self.memory_budget = memory_budget;
+        self.shrink();
+    }
+
+    fn shrink(&mut self) {
+        while self.memory_usage > self.memory_budget {
+            match self.undo_stack.pop_front() {
+                Some((_, size)) => self.memory_usage -= size,
+                None => break,
+            }
+        }
+        if self.memory_usage > self.memory_budget {
+            for (_, size) in self.redo_stack.drain(..) {
+                self.memory_usage -= size;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const WHITE: RGB = RGB::new(0xff, 0xff, 0xff);
+    const BLACK: RGB = RGB::new(0, 0, 0);
+
+    fn layers() -> Vec<Layer> {
+        vec![Layer::new("Background".to_string(), (4, 4), WHITE)]
+    }
+
+    #[test]
+    fn test_undo_redo() {
+        let mut layers = layers();
+        let mut history = History::new(DEFAULT_MEMORY_BUDGET);
+        let mut step = PixelStep::new(0);
+        layers[0].image.set(1, 2, BLACK);
+        step.push(1, 2, WHITE, BLACK);
+        history.push(Step::Pixels(step));
+        assert!(history.undo(&mut layers).is_some());
+        assert_eq!(layers[0].image.get(1, 2), WHITE);
+        assert!(history.undo(&mut layers).is_none());
+        assert!(history.redo(&mut layers).is_some());
+        assert_eq!(layers[0].image.get(1, 2), BLACK);
+        assert!(history.redo(&mut layers).is_none());
+    }
+
+    #[test]
+    fn test_undo_layer_operations() {
+        let mut layers = layers();
+        let mut history = History::new(DEFAULT_MEMORY_BUDGET);
+        let layer = Layer::new("Layer 1".to_string(), (4, 4), RGB::TRANSPARENT);
+        layers.push(layer.clone());
+        history.push(Step::InsertLayer { index: 1, layer });
+        layers.swap(0, 1);
+        history.push(Step::MoveLayer { from: 1, to: 0 });
+        assert_eq!(history.undo(&mut layers).unwrap().0, 1);
+        assert_eq!(layers[1].name, "Layer 1");
+        history.undo(&mut layers);
+        assert_eq!(layers.len(), 1);
+        history.redo(&mut layers);
+        assert_eq!(layers[1].name, "Layer 1");
+    }
+
+    #[test]
+    fn test_undo_layer_properties() {
+        let mut layers = layers();
+        let mut history = History::new(DEFAULT_MEMORY_BUDGET);
+        for &opacity in &[0.8, 0.5] {
+            let before = (layers[0].visible, layers[0].opacity);
+            layers[0].opacity = opacity;
+            history.push(Step::LayerProperties {
+                index: 0,
+                before,
+                after: (true, opacity),
+            });
+        }
+        history.undo(&mut layers);
+        assert_eq!(layers[0].opacity, 1.0);
+        assert!(history.undo(&mut layers).is_none());
+        history.redo(&mut layers);
+        assert_eq!(layers[0].opacity, 0.5);
+    }
+
+    #[test]
+    fn test_undo_grow() {
+        let mut layers = layers();
+        let mut history = History::new(DEFAULT_MEMORY_BUDGET);
+        let step = GrowStep {
+            layer: 0,
+            before: (4, 4),
+            after: (6, 5),
+            offset: (2, 1),
+            background: WHITE,
+        };
+        layers[0].image.set(0, 0, BLACK);
+        step.apply(&mut layers, false);
+        assert_eq!(layers[0].image.get(2, 1), BLACK);
+        history.push(Step::Grow(step));
+        let (_, area, shift) = history.undo(&mut layers).unwrap();
+        assert_eq!((area.width, area.height, shift), (4.0, 4.0, (-2, -1)));
+        assert_eq!(layers[0].image.get(0, 0), BLACK);
+        assert_eq!(history.redo(&mut layers).unwrap().2, (2, 1));
+        assert_eq!(layers[0].image.width, 6);
+    }
+
+    #[test]
+    fn test_memory_budget() {
+        let mut layers = layers();
+        let mut history = History::new(size_of::<PixelChange>());
+        for x in 0..2 {
+            let mut step = PixelStep::new(0);
+            step.push(x, 0, WHITE, BLACK);
+            history.push(Step::Pixels(step));
+        }
+        assert!(history.undo(&mut layers).is_some());
+        assert!(history.undo(&mut layers).is_none());
+    }
+
+    #[test]
+    fn test_shared_tiles_not_counted() {
+        let mut layers = vec![Layer::new(
+            "Background".to_string(),
+            (4 * TILE_SIZE, 4 * TILE_SIZE),
+            WHITE,
+        )];
+        for y in 0..4 * TILE_SIZE {
+            layers[0].image.set(0, y, BLACK);
+        }
+        let tile = 4 * TILE_SIZE * TILE_SIZE;
+        // Growing to the right and bottom keeps the tiles where they are.
+        let before = layers.clone();
+        layers[0]
+            .image
+            .resize(5 * TILE_SIZE, 5 * TILE_SIZE, (0, 0), WHITE);
+        let step = Step::ReplaceLayers {
+            before,
+            after: layers.clone(),
+        };
+        assert_eq!(step.memory_size(), 0);
+        let mut history = History::new(tile);
+        history.push(step);
+        assert!(history.undo(&mut layers).is_some());
+        assert_eq!(layers[0].image.width, 4 * TILE_SIZE);
+        drop(history);
+        // A removed layer is only kept by the step.
+        let step = Step::RemoveLayer {
+            index: 0,
+            layer: layers.pop().unwrap(),
+        };
+        assert_eq!(step.memory_size(), 4 * tile);
+    }
+}
@@ -0,0 +1,66 @@
+use super::*;
+
+#[derive(Debug, Clone)]
+pub struct Layer {
+    pub name: String,
+    pub image: TiledImage,
+    pub visible: bool,
+    pub opacity: f64,
+}
+
+impl Layer {
+    pub fn new(name: String, size: (usize, usize), fill: RGB) -> Layer {
+        Layer {
+            name,
+            image: TiledImage::new(size.0, size.1, fill),
+            visible: true,
+            opacity: 1.0,
+        }
+    }
+}
+
+/// Blends the fill colors of the visible layers, which is the composite
+/// wherever no layer is painted.
+pub fn composite_fill(layers: &[Layer]) -> RGB {
+    layers
+        .iter()
+        .filter(|l| l.visible)
+        .fold(RGB::TRANSPARENT, |below, l| {
+            below.over(l.image.fill(), l.opacity)
+        })
+}
+
+/// Blends the visible layers at (`x`, `y`) from bottom to top.
+pub fn composite(layers: &[Layer], x: usize, y: usize) -> RGB {
+    layers
+        .iter()
+        .filter(|l| l.visible)
+        .fold(RGB::TRANSPARENT, |below, l| {
+            below.over(l.image.get(x, y), l.opacity)
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_composite() {
+        let white = RGB::new(0xff, 0xff, 0xff);
+        let mut layers = vec![
+            Layer::new("Background".to_string(), (1, 1), white),
+            Layer::new("Layer 1".to_string(), (1, 1), RGB::TRANSPARENT),
+        ];
+        assert_eq!(composite(&layers, 0, 0), white);
+        layers[1].image.set(0, 0, RGB::new(0, 0, 0));
+        layers[1].opacity = 0.5;
+        assert_eq!(composite(&layers, 0, 0), RGB::new(0x7f, 0x7f, 0x7f));
+        layers[1].visible = false;
+        assert_eq!(composite(&layers, 0, 0), white);
+        layers[0].opacity = 0.5;
+        assert_eq!(
+            composite(&layers, 0, 0),
+            RGB::with_alpha(0xff, 0xff, 0xff, 0x80)
+        );
+    }
+}
@@ -0,0 +1,339 @@
+use std::collections::HashSet;
+
+use super::*;
+
+/// How the pen paints.
+pub struct PenSetting {
+    pub size: f64,
+    pub opacity: f64,
+    pub color: RGB,
+    pub background_color: RGB,
+    pub antialias: bool,
+    /// Erases to transparency instead of painting `color`.
+    pub eraser: bool,
+    pub pressure_curve: PressureCurve,
+}
+
+/// Maps the pressure reported by the device to the pen size and opacity.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PressureCurve {
+    /// Exponent applied to the pressure. Above 1 a harder press is needed.
+    pub gamma: f64,
+    /// Size at zero pressure, relative to the pen size.
+    pub min_size: f64,
+    /// Whether the pressure also changes the opacity.
+    pub opacity: bool,
+}
+
+impl PressureCurve {
+    pub const LINEAR: PressureCurve = PressureCurve {
+        gamma: 1.0,
+        min_size: 0.0,
+        opacity: false,
+    };
+
+    /// Returns the pressure after the curve, from 0 to 1.
+    pub fn apply(&self, pressure: f64) -> f64 {
+        pressure.clamp(0.0, 1.0).powf(self.gamma)
+    }
+
+    pub fn size(&self, pressure: f64, size: f64) -> f64 {
+        size * (self.min_size + (1.0 - self.min_size) * self.apply(pressure))
+    }
+
+    pub fn opacity(&self, pressure: f64) -> f64 {
+        if self.opacity {
+            self.apply(pressure)
+        } else {
+            1.0
+        }
+    }
+}
+
+fn rectangle(r: i32) -> impl Iterator<Item = (i32, i32)> {
+    (-r..=r).flat_map(move |x| (-r..=r).map(move |y| (x, y)))
+}
+
+fn circle(r: f64, x: f64, y: f64) -> impl Iterator<Item = (i32, i32)> {
+    let p_x = x;
+    let p_y = y;
+    rectangle(r.ceil() as i32)
+        .filter(move |(x, y)| {
+            let dx = x.abs();
+            let dy = y.abs();
+            ((dx.pow(2) + dy.pow(2)) as f64) < r.powi(2)
+        })
+        .map(move |(x, y)| {
+            let x = x + p_x.floor() as i32;
+            let y = y + p_y.floor() as i32;
+            (x, y)
+        })
+}
+
+fn rect(
+    xr: std::ops::RangeInclusive<i32>,
+    yr: std::ops::RangeInclusive<i32>,
+) -> impl Iterator<Item = (i32, i32)> {
+    xr.flat_map(move |x| yr.clone().map(move |y| (x, y)))
+}
+
+fn line(
+    r1: f64,
+    x1: f64,
+    y1: f64,
+    r2: f64,
+    x2: f64,
+    y2: f64,
+) -> impl Iterator<Item = (i32, i32)> {
+    let x1 = x1.floor();
+    let x2 = x2.floor();
+    let y1 = y1.floor();
+    let y2 = y2.floor();
+    let min = |a1: f64, a2: f64| {
+        (if a1 < a2 { a1 - r1 } else { a2 - r2 }).floor() as i32
+    };
+    let max = |a1: f64, a2: f64| {
+        (if a1 < a2 { a2 + r2 } else { a1 + r1 }).ceil() as i32
+    };
+    let dx = x2 - x1;
+    let dy = y2 - y1;
+    let hx = dy;
+    let hy = -dx;
+    rect(min(x1, x2)..=max(x1, x2), min(y1, y2)..=max(y1, y2)).filter(
+        move |(x, y)| {
+            let relative_x = *x as f64 - x1;
+            let relative_y = *y as f64 - y1;
+            let len_pow_2 = dx.powi(2) + dy.powi(2);
+            let ratio_mul_len_pow_2 = dx * relative_x + dy * relative_y;
+            if ratio_mul_len_pow_2 < 0.0 || len_pow_2 < ratio_mul_len_pow_2 {
+                false
+            } else {
+                let border_d_mul_len_pow_2 = ratio_mul_len_pow_2 * r2
+                    + (len_pow_2 - ratio_mul_len_pow_2) * r1;
+                let d_mul_len = (hx * relative_x + hy * relative_y).abs();
+                border_d_mul_len_pow_2 > d_mul_len * len_pow_2.sqrt()
+            }
+        },
+    )
+}
+
+/// Coverage of the pixels by the convex hull of two circles. Each pixel is
+/// sampled at its center, using the distance to the outline of the shape.
+fn smooth_line(
+    r1: f64,
+    x1: f64,
+    y1: f64,
+    r2: f64,
+    x2: f64,
+    y2: f64,
+) -> impl Iterator<Item = ((i32, i32), f64)> {
+    let min = |a1: f64, a2: f64| (a1 - r1).min(a2 - r2).floor() as i32 - 1;
+    let max = |a1: f64, a2: f64| (a1 + r1).max(a2 + r2).ceil() as i32 + 1;
+    let dx = x2 - x1;
+    let dy = y2 - y1;
+    let len_pow_2 = dx.powi(2) + dy.powi(2);
+    rect(min(x1, x2)..=max(x1, x2), min(y1, y2)..=max(y1, y2)).filter_map(
+        move |(x, y)| {
+            let px = x as f64 + 0.5;
+            let py = y as f64 + 0.5;
+            let distance = |cx: f64, cy: f64, r: f64| {
+                ((px - cx).powi(2) + (py - cy).powi(2)).sqrt() - r
+            };
+            let mut d = distance(x1, y1, r1).min(distance(x2, y2, r2));
+            if len_pow_2 > 0.0 {
+                let t = ((px - x1) * dx + (py - y1) * dy) / len_pow_2;
+                if 0.0 < t && t < 1.0 {
+                    d = d.min(distance(
+                        x1 + t * dx,
+                        y1 + t * dy,
+                        r1 + t * (r2 - r1),
+                    ));
+                }
+            }
+            let coverage = (0.5 - d).min(1.0);
+            if coverage > 0.0 {
+                Some(((x, y), coverage))
+            } else {
+                None
+            }
+        },
+    )
+}
+
+fn pressure_to_radias(p: f64, setting: &PenSetting) -> f64 {
+    setting.pressure_curve.size(p, setting.size)
+}
+
+/// Returns the smallest and largest x and y of the pixels the pen may touch
+/// from `previous_input` to `input`.
+pub fn bounds(
+    input: &PenInput,
+    previous_input: &Option<PenInput>,
+    setting: &PenSetting,
+) -> (f64, f64, f64, f64) {
+    let previous = previous_input.unwrap_or(*input);
+    let r = pressure_to_radias(input.pressure, setting)
+        .max(pressure_to_radias(previous.pressure, setting))
+        + 1.0;
+    (
+        input.x.min(previous.x) - r,
+        input.y.min(previous.y) - r,
+        input.x.max(previous.x) + r,
+        input.y.max(previous.y) + r,
+    )
+}
+
+fn circle_pen_outline(
+    input: &PenInput,
+    previous_input: &Option<PenInput>,
+    setting: &PenSetting,
+) -> HashSet<(i32, i32)> {
+    match previous_input {
+        None => circle(
+            pressure_to_radias(input.pressure, setting),
+            input.x,
+            input.y,
+        )
+        .collect(),
+        Some(previous_input) => {
+            let previous_size =
+                pressure_to_radias(previous_input.pressure, setting);
+            let size = pressure_to_radias(input.pressure, setting);
+            circle(previous_size, previous_input.x, previous_input.y)
+                .chain(circle(size, input.x, input.y))
+                .chain(line(
+                    previous_size,
+                    previous_input.x,
+                    previous_input.y,
+                    size,
+                    input.x,
+                    input.y,
+                ))
+                .collect()
+        }
+    }
+}
+
+/// Returns the pixels touched by the pen and how much each of them is
+/// covered, from 0 exclusive to 1.
+pub fn circle_pen(
+    input: &PenInput,
+    previous_input: &Option<PenInput>,
+    setting: &PenSetting,
+) -> Box<dyn Iterator<Item = ((i32, i32), f64)>> {
+    if setting.antialias {
+        let size = pressure_to_radias(input.pressure, setting);
+        let (previous_size, previous_x, previous_y) = match previous_input {
+            Some(p) => (pressure_to_radias(p.pressure, setting), p.x, p.y),
+            None => (size, input.x, input.y),
+        };
+        Box::new(smooth_line(
+            previous_size,
+            previous_x,
+            previous_y,
+            size,
+            input.x,
+            input.y,
+        ))
+    } else {
+        let h = circle_pen_outline(input, previous_input, setting);
+        Box::new(h.into_iter().map(|p| (p, 1.0)))
+    }
+}
+
+#[allow(dead_code)]
+pub fn debug_pen(
+    input: &PenInput,
+    previous_input: &Option<PenInput>,
+    setting: &PenSetting,
+) -> impl Iterator<Item = ((i32, i32), RGB)> {
+    let blue = RGB::new(0x03, 0xfc, 0xcf);
+    match previous_input {
+        None => circle(
+            pressure_to_radias(input.pressure, setting),
+            input.x,
+            input.y,
+        )
+        .map(|p| (p, blue))
+        .collect::<Vec<_>>()
+        .into_iter(),
+        Some(previous_input) => {
+            let previous_size =
+                pressure_to_radias(previous_input.pressure, setting);
+            let size = pressure_to_radias(input.pressure, setting);
+            line(
+                previous_size,
+                previous_input.x,
+                previous_input.y,
+                size,
+                input.x,
+                input.y,
+            )
+            .map(|p| (p, RGB::new(0, 0, 0)))
+            .chain(
+                circle(previous_size, previous_input.x, previous_input.y)
+                    .map(|p| (p, blue)),
+            )
+            .chain(circle(size, input.x, input.y).map(|p| (p, blue)))
+            .collect::<Vec<_>>()
+            .into_iter()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::{HashMap, HashSet};
+
+    use super::*;
+
+    #[test]
+    fn test_round() {
+        assert_eq!(2.6f64.round(), 3.0);
+    }
+    #[test]
+    fn test_smooth_line_coverage() {
+        let coverage = smooth_line(2.0, 10.0, 10.0, 2.0, 10.0, 10.0)
+            .collect::<HashMap<_, _>>();
+        assert_eq!(coverage[&(9, 9)], 1.0);
+        assert!(!coverage.contains_key(&(13, 13)));
+        let edge = coverage[&(11, 8)];
+        assert!(0.0 < edge && edge < 1.0);
+    }
+
+    #[test]
+    fn test_pressure_curve() {
+        assert_eq!(PressureCurve::LINEAR.size(0.5, 20.0), 10.0);
+        assert_eq!(PressureCurve::LINEAR.opacity(0.5), 1.0);
+        let curve = PressureCurve {
+            gamma: 2.0,
+            min_size: 0.5,
+            opacity: true,
+        };
+        assert_eq!(curve.size(0.0, 20.0), 10.0);
+        assert_eq!(curve.size(0.5, 20.0), 12.5);
+        assert_eq!(curve.size(2.0, 20.0), 20.0);
+        assert_eq!(curve.opacity(0.5), 0.25);
+    }
+
+    #[test]
+    fn test_rectangle_pen() {
+        assert_eq!(
+            rectangle(1).collect::<HashSet<_>>(),
+            [
+                (-1, -1),
+                (0, -1),
+                (1, -1),
+                (-1, 0),
+                (0, 0),
+                (1, 0),
+                (-1, 1),
+                (0, 1),
+                (1, 1),
+            ]
+            .iter()
+            .copied()
+            .collect::<HashSet<_>>()
+        );
+    }
+}
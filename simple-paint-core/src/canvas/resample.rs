@@ -0,0 +1,195 @@
+use super::*;
+
+/// Algorithm used to scale an image.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Resample {
+    Nearest,
+    Bilinear,
+    /// Catmull-Rom spline, sharper than bilinear.
+    Bicubic,
+    /// Averages the covered pixels, which suits shrinking.
+    Area,
+}
+
+fn catmull_rom(t: f64) -> f64 {
+    let t = t.abs();
+    if t < 1.0 {
+        1.5 * t.powi(3) - 2.5 * t.powi(2) + 1.0
+    } else if t < 2.0 {
+        -0.5 * t.powi(3) + 2.5 * t.powi(2) - 4.0 * t + 2.0
+    } else {
+        0.0
+    }
+}
+
+/// Returns for each of `dst` pixels the source pixels and their weights
+/// along one axis of `src` pixels.
+fn weights(src: usize, dst: usize, method: Resample) -> Vec<Vec<(usize, f64)>> {
+    let ratio = src as f64 / dst as f64;
+    let clamp = |i: i64| i.clamp(0, src as i64 - 1) as usize;
+    (0..dst)
+        .map(|i| {
+            let center = (i as f64 + 0.5) * ratio - 0.5;
+            let i0 = center.floor();
+            let f = center - i0;
+            let i0 = i0 as i64;
+            match method {
+                Resample::Nearest => {
+                    vec![(clamp(((i as f64 + 0.5) * ratio) as i64), 1.0)]
+                }
+                Resample::Bilinear => {
+                    vec![(clamp(i0), 1.0 - f), (clamp(i0 + 1), f)]
+                }
+                Resample::Bicubic => (-1..=2)
+                    .map(|k| (clamp(i0 + k), catmull_rom(f - k as f64)))
+                    .collect(),
+                Resample::Area => {
+                    let (start, end) =
+                        (i as f64 * ratio, (i + 1) as f64 * ratio);
+                    (start.floor() as usize..(end.ceil() as usize).min(src))
+                        .map(|j| {
+                            let overlap =
+                                end.min(j as f64 + 1.0) - start.max(j as f64);
+                            (j, overlap / ratio)
+                        })
+                        .collect()
+                }
+            }
+        })
+        .collect()
+}
+
+/// Scales `image` to `width` x `height` pixels. The edges are extended
+/// rather than faded to transparent. The image is scaled in bands of
+/// `TILE_SIZE` rows so that only the source rows of one band are kept
+/// scaled horizontally at a time.
+pub fn scale(
+    image: &TiledImage,
+    width: usize,
+    height: usize,
+    method: Resample,
+) -> TiledImage {
+    let xs = weights(image.width, width, method);
+    let ys = weights(image.height, height, method);
+    let mut scaled = TiledImage::new(width, height, image.fill());
+    // Premultiplied channels in the order of `RGB::array`.
+    let mut rows: Vec<[f32; 4]> = Vec::new();
+    for (band, band_ys) in ys.chunks(TILE_SIZE).enumerate() {
+        let sources = band_ys.iter().flatten().map(|&(sy, _)| sy);
+        let first = sources.clone().min().unwrap_or(0);
+        let last = sources.max().unwrap_or(0);
+        rows.clear();
+        rows.resize(width * (last + 1 - first), [0.0; 4]);
+        for y in first..=last {
+            for (x, ws) in xs.iter().enumerate() {
+                let p = &mut rows[(y - first) * width + x];
+                for &(sx, w) in ws {
+                    let c = image.get(sx, y);
+                    for (p, c) in p.iter_mut().zip(&c.array) {
+                        *p += *c as f32 * w as f32;
+                    }
+                }
+            }
+        }
+        for (i, ws) in band_ys.iter().enumerate() {
+            let y = band * TILE_SIZE + i;
+            for x in 0..width {
+                let mut p = [0.0; 4];
+                for &(sy, w) in ws {
+                    let row = &rows[(sy - first) * width + x];
+                    for (p, c) in p.iter_mut().zip(row) {
+                        *p += c * w as f32;
+                    }
+                }
+                // Bicubic overshoots, so keep the colors within the alpha.
+                let a = p[3].round().clamp(0.0, 255.0);
+                let mut array = [0; 4];
+                for (c, p) in array.iter_mut().zip(&p) {
+                    *c = p.round().clamp(0.0, a) as u8;
+                }
+                scaled.set(x, y, RGB { array });
+            }
+        }
+    }
+    scaled
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const WHITE: RGB = RGB::new(0xff, 0xff, 0xff);
+    const BLACK: RGB = RGB::new(0, 0, 0);
+
+    fn checker(size: usize) -> TiledImage {
+        let mut image = TiledImage::new(size, size, WHITE);
+        for y in 0..size {
+            for x in 0..size {
+                if (x + y) % 2 == 0 {
+                    image.set(x, y, BLACK);
+                }
+            }
+        }
+        image
+    }
+
+    #[test]
+    fn test_scale_up() {
+        let image = checker(2);
+        let nearest = scale(&image, 4, 4, Resample::Nearest);
+        assert_eq!(nearest.get(1, 1), BLACK);
+        assert_eq!(nearest.get(2, 1), WHITE);
+        let bilinear = scale(&image, 4, 4, Resample::Bilinear);
+        assert_eq!(bilinear.get(0, 0), BLACK);
+        assert_ne!(bilinear.get(1, 1), BLACK);
+        let bicubic = scale(&image, 4, 4, Resample::Bicubic);
+        assert_ne!(bicubic.get(1, 1), BLACK);
+    }
+
+    #[test]
+    fn test_scale_down() {
+        let image = checker(4);
+        let area = scale(&image, 2, 2, Resample::Area);
+        assert_eq!(area.get(0, 0), RGB::new(0x80, 0x80, 0x80));
+        let nearest = scale(&image, 2, 2, Resample::Nearest);
+        assert_eq!(nearest.get(0, 0), BLACK);
+        let same = scale(&image, 4, 4, Resample::Bicubic);
+        assert_eq!(same.to_image().vector, image.to_image().vector);
+    }
+
+    #[test]
+    fn test_scale_bands() {
+        // Taller than a band, with the bands sharing source rows.
+        let mut image = TiledImage::new(40, 3 * TILE_SIZE / 2, WHITE);
+        for y in 0..image.height {
+            image.set(y % 40, y, RGB::with_alpha(0, 0, 0x80, 0x80));
+        }
+        let height = 5 * TILE_SIZE / 2;
+        let scaled = scale(&image, 30, height, Resample::Bicubic);
+        let ys = weights(image.height, height, Resample::Bicubic);
+        let xs = weights(image.width, 30, Resample::Bicubic);
+        for (y, wy) in ys.iter().enumerate() {
+            for (x, wx) in xs.iter().enumerate() {
+                let mut alpha = 0.0;
+                let mut blue = 0.0;
+                for &(sy, a) in wy {
+                    for &(sx, b) in wx {
+                        let c = image.get(sx, sy);
+                        alpha += c.array[3] as f64 * a * b;
+                        blue += c.array[0] as f64 * a * b;
+                    }
+                }
+                let c = scaled.get(x, y);
+                let alpha = alpha.round().clamp(0.0, 255.0);
+                let blue = blue.round().clamp(0.0, alpha);
+                assert!(
+                    (c.array[3] as f64 - alpha).abs() <= 1.0,
+                    "{} {}",
+                    x,
+                    y
+                );
+                assert!((c.array[0] as f64 - blue).abs() <= 1.0, "{} {}", x, y);
+            }
+        }
+    }
+}
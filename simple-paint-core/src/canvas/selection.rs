@@ -0,0 +1,228 @@
+use super::shape::{self, Shape};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SelectionShape {
+    Rectangle,
+    Ellipse,
+    /// Freehand outline following the pointer.
+    Lasso,
+}
+
+impl SelectionShape {
+    /// Returns the polygon selected by dragging through `points`.
+    pub fn polygon(self, points: &[(f64, f64)]) -> Vec<(f64, f64)> {
+        let (first, last) = match (points.first(), points.last()) {
+            (Some(&first), Some(&last)) => (first, last),
+            _ => return Vec::new(),
+        };
+        match self {
+            SelectionShape::Rectangle => {
+                shape::outline(Shape::Rectangle, first, last)
+            }
+            SelectionShape::Ellipse => {
+                shape::outline(Shape::Ellipse, first, last)
+            }
+            SelectionShape::Lasso => points.to_vec(),
+        }
+    }
+}
+
+/// How a new selection is combined with the current one.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SelectionMode {
+    Replace,
+    Add,
+    Subtract,
+    Intersect,
+}
+
+/// The selected pixels of the canvas.
+#[derive(Debug, Clone)]
+pub struct Selection {
+    width: usize,
+    height: usize,
+    mask: Vec<bool>,
+}
+
+impl Selection {
+    pub fn all(width: usize, height: usize) -> Selection {
+        Selection {
+            width,
+            height,
+            mask: vec![true; width * height],
+        }
+    }
+
+    /// Selects the pixels whose centers are inside `polygon`, using the
+    /// even-odd rule. Points that are not finite are skipped.
+    pub fn from_polygon(
+        polygon: &[(f64, f64)],
+        width: usize,
+        height: usize,
+    ) -> Selection {
+        let mut mask = vec![false; width * height];
+        let polygon = polygon
+            .iter()
+            .filter(|(x, y)| x.is_finite() && y.is_finite())
+            .collect::<Vec<_>>();
+        let edges = polygon
+            .iter()
+            .zip(polygon.iter().cycle().skip(1))
+            .collect::<Vec<_>>();
+        let mut crossings = Vec::new();
+        for y in 0..height {
+            let cy = y as f64 + 0.5;
+            crossings.clear();
+            for &(&&(x1, y1), &&(x2, y2)) in &edges {
+                if (y1 <= cy) != (y2 <= cy) {
+                    crossings.push(x1 + (cy - y1) / (y2 - y1) * (x2 - x1));
+                }
+            }
+            crossings.sort_by(f64::total_cmp);
+            for pair in crossings.chunks_exact(2) {
+                // Pixels with pair[0] <= x + 0.5 < pair[1].
+                let start = (pair[0] - 0.5).ceil().max(0.0) as usize;
+                let end = ((pair[1] - 0.5).ceil().max(0.0) as usize).min(width);
+                for x in start..end {
+                    mask[y * width + x] = true;
+                }
+            }
+        }
+        Selection {
+            width,
+            height,
+            mask,
+        }
+    }
+
+    pub fn contains(&self, x: usize, y: usize) -> bool {
+        self.mask[y * self.width + x]
+    }
+
+    pub fn is_empty(&self) -> bool {
+        !self.mask.contains(&true)
+    }
+
+    pub fn combine(&mut self, other: &Selection, mode: SelectionMode) {
+        for (a, b) in self.mask.iter_mut().zip(&other.mask) {
+            *a = match mode {
+                SelectionMode::Replace => *b,
+                SelectionMode::Add => *a || *b,
+                SelectionMode::Subtract => *a && !*b,
+                SelectionMode::Intersect => *a && *b,
+            };
+        }
+    }
+
+    pub fn invert(&mut self) {
+        for a in &mut self.mask {
+            *a = !*a;
+        }
+    }
+
+    fn get(&self, x: isize, y: isize) -> bool {
+        0 <= x
+            && 0 <= y
+            && (x as usize) < self.width
+            && (y as usize) < self.height
+            && self.contains(x as usize, y as usize)
+    }
+
+    /// Returns the borders between selected and unselected pixels as line
+    /// segments in canvas coordinates.
+    pub fn outline(&self) -> Vec<((f64, f64), (f64, f64))> {
+        let (width, height) = (self.width as isize, self.height as isize);
+        let mut segments = Vec::new();
+        // Runs of horizontal borders, then of vertical ones.
+        for y in 0..=height {
+            let mut start = None;
+            for x in 0..=width {
+                let border = x < width && self.get(x, y - 1) != self.get(x, y);
+                match (start, border) {
+                    (None, true) => start = Some(x),
+                    (Some(s), false) => {
+                        segments
+                            .push(((s as f64, y as f64), (x as f64, y as f64)));
+                        start = None;
+                    }
+                    _ => (),
+                }
+            }
+        }
+        for x in 0..=width {
+            let mut start = None;
+            for y in 0..=height {
+                let border = y < height && self.get(x - 1, y) != self.get(x, y);
+                match (start, border) {
+                    (None, true) => start = Some(y),
+                    (Some(s), false) => {
+                        segments
+                            .push(((x as f64, s as f64), (x as f64, y as f64)));
+                        start = None;
+                    }
+                    _ => (),
+                }
+            }
+        }
+        segments
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn count(s: &Selection) -> usize {
+        s.mask.iter().filter(|m| **m).count()
+    }
+
+    #[test]
+    fn test_from_polygon() {
+        let square =
+            SelectionShape::Rectangle.polygon(&[(1.0, 1.0), (4.0, 3.0)]);
+        let s = Selection::from_polygon(&square, 6, 6);
+        assert_eq!(count(&s), 6);
+        assert!(s.contains(1, 1) && s.contains(3, 2) && !s.contains(4, 2));
+        let outside = Selection::from_polygon(&square, 2, 2);
+        assert_eq!(count(&outside), 1);
+        let mut broken = square.clone();
+        broken.insert(2, (f64::NAN, 2.0));
+        assert_eq!(count(&Selection::from_polygon(&broken, 6, 6)), 6);
+    }
+
+    #[test]
+    fn test_combine() {
+        let left = Selection::from_polygon(
+            &SelectionShape::Rectangle.polygon(&[(0.0, 0.0), (2.0, 1.0)]),
+            4,
+            1,
+        );
+        let right = Selection::from_polygon(
+            &SelectionShape::Rectangle.polygon(&[(1.0, 0.0), (4.0, 1.0)]),
+            4,
+            1,
+        );
+        let combined = |mode| {
+            let mut s = left.clone();
+            s.combine(&right, mode);
+            s.mask
+        };
+        assert_eq!(combined(SelectionMode::Add), [true; 4]);
+        assert_eq!(
+            combined(SelectionMode::Subtract),
+            [true, false, false, false]
+        );
+        assert_eq!(
+            combined(SelectionMode::Intersect),
+            [false, true, false, false]
+        );
+    }
+
+    #[test]
+    fn test_outline() {
+        let mut s = Selection::from_polygon(&[], 3, 3);
+        s.mask[4] = true;
+        assert_eq!(s.outline().len(), 4);
+        assert_eq!(Selection::all(3, 3).outline().len(), 4);
+    }
+}
@@ -0,0 +1,97 @@
+use std::f64::consts::PI;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Shape {
+    Line,
+    Rectangle,
+    Ellipse,
+    /// Closed polygon whose vertices are added one by one. Each edge is
+    /// dragged like a line.
+    Polygon,
+}
+
+/// Step of the angle of constrained lines.
+const ANGLE_STEP: f64 = PI / 12.0;
+
+/// Moves `end` so that lines from `start` are at a multiple of 15 degrees
+/// and rectangles and ellipses become squares and circles.
+pub fn constrain(
+    shape: Shape,
+    start: (f64, f64),
+    end: (f64, f64),
+) -> (f64, f64) {
+    let dx = end.0 - start.0;
+    let dy = end.1 - start.1;
+    match shape {
+        Shape::Line | Shape::Polygon => {
+            let angle = (dy.atan2(dx) / ANGLE_STEP).round() * ANGLE_STEP;
+            let length = dx.hypot(dy);
+            (
+                start.0 + length * angle.cos(),
+                start.1 + length * angle.sin(),
+            )
+        }
+        Shape::Rectangle | Shape::Ellipse => {
+            let size = dx.abs().max(dy.abs());
+            (start.0 + size.copysign(dx), start.1 + size.copysign(dy))
+        }
+    }
+}
+
+/// Returns the outline of the shape dragged from `start` to `end` as a
+/// polyline. For a polygon this is the edge being dragged.
+pub fn outline(
+    shape: Shape,
+    start: (f64, f64),
+    end: (f64, f64),
+) -> Vec<(f64, f64)> {
+    match shape {
+        Shape::Line | Shape::Polygon => vec![start, end],
+        Shape::Rectangle => {
+            vec![start, (end.0, start.1), end, (start.0, end.1), start]
+        }
+        Shape::Ellipse => {
+            let (cx, cy) = ((start.0 + end.0) / 2.0, (start.1 + end.1) / 2.0);
+            let (rx, ry) = ((end.0 - cx).abs(), (end.1 - cy).abs());
+            // Segments about 2 pixels long.
+            let n = ((PI * (rx + ry) / 2.0).ceil() as usize).max(16);
+            (0..=n)
+                .map(|i| {
+                    let t = 2.0 * PI * i as f64 / n as f64;
+                    (cx + rx * t.cos(), cy + ry * t.sin())
+                })
+                .collect()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_near(a: (f64, f64), b: (f64, f64)) {
+        assert!((a.0 - b.0).abs() < 1e-9 && (a.1 - b.1).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_constrain() {
+        assert_near(
+            constrain(Shape::Line, (0.0, 0.0), (10.0, 1.0)),
+            (10.0_f64.hypot(1.0), 0.0),
+        );
+        let (x, y) = constrain(Shape::Line, (0.0, 0.0), (10.0, 9.0));
+        assert!((x - y).abs() < 1e-9);
+        assert_near(
+            constrain(Shape::Rectangle, (1.0, 1.0), (-3.0, 2.0)),
+            (-3.0, 5.0),
+        );
+    }
+
+    #[test]
+    fn test_outline_is_closed() {
+        for &shape in &[Shape::Rectangle, Shape::Ellipse] {
+            let points = outline(shape, (0.0, 0.0), (20.0, 10.0));
+            assert_near(points[0], *points.last().unwrap());
+        }
+    }
+}
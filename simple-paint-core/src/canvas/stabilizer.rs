@@ -0,0 +1,203 @@
+use std::collections::VecDeque;
+
+use super::*;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum StabilizerMode {
+    /// Exponentially weighted moving average of the input positions.
+    Average,
+    /// The brush is pulled by a rope and stays still while the pointer moves
+    /// within the rope length.
+    Rope,
+}
+
+/// Distance in pixels of the longest rope, used at strength 1.
+const MAX_ROPE_LENGTH: f64 = 60.0;
+
+/// Distance in pixels between points interpolated on the curve.
+const INTERPOLATION_STEP: f64 = 2.0;
+
+/// Smooths a stream of pen inputs and interpolates a Catmull-Rom spline
+/// through the smoothed points, so that fast strokes stay round.
+pub struct Stabilizer {
+    pub mode: StabilizerMode,
+    /// From 0 (no smoothing) to 1.
+    pub strength: f64,
+    raw: Option<PenInput>,
+    smoothed: Option<PenInput>,
+    /// The last control points of the spline, at most four.
+    points: VecDeque<PenInput>,
+}
+
+fn lerp(a: PenInput, b: PenInput, t: f64) -> PenInput {
+    PenInput {
+        x: a.x + (b.x - a.x) * t,
+        y: a.y + (b.y - a.y) * t,
+        pressure: a.pressure + (b.pressure - a.pressure) * t,
+    }
+}
+
+fn distance(a: PenInput, b: PenInput) -> f64 {
+    (b.x - a.x).hypot(b.y - a.y)
+}
+
+/// Points of the Catmull-Rom segment from `p1` to `p2`, excluding `p1`.
+fn catmull_rom(
+    p0: PenInput,
+    p1: PenInput,
+    p2: PenInput,
+    p3: PenInput,
+) -> impl Iterator<Item = PenInput> {
+    let n = (distance(p1, p2) / INTERPOLATION_STEP).ceil().max(1.0) as usize;
+    let spline = |a: f64, b: f64, c: f64, d: f64, t: f64| {
+        0.5 * (2.0 * b
+            + (c - a) * t
+            + (2.0 * a - 5.0 * b + 4.0 * c - d) * t.powi(2)
+            + (3.0 * b - a - 3.0 * c + d) * t.powi(3))
+    };
+    (1..=n).map(move |i| {
+        let t = i as f64 / n as f64;
+        PenInput {
+            x: spline(p0.x, p1.x, p2.x, p3.x, t),
+            y: spline(p0.y, p1.y, p2.y, p3.y, t),
+            pressure: p1.pressure + (p2.pressure - p1.pressure) * t,
+        }
+    })
+}
+
+impl Default for Stabilizer {
+    fn default() -> Stabilizer {
+        Stabilizer::new()
+    }
+}
+
+impl Stabilizer {
+    pub fn new() -> Stabilizer {
+        Stabilizer {
+            mode: StabilizerMode::Average,
+            strength: 0.0,
+            raw: None,
+            smoothed: None,
+            points: VecDeque::new(),
+        }
+    }
+
+    fn smooth(&mut self, input: PenInput) -> Option<PenInput> {
+        self.raw = Some(input);
+        let previous = match self.smoothed {
+            Some(p) => p,
+            None => {
+                self.smoothed = Some(input);
+                return Some(input);
+            }
+        };
+        let next = match self.mode {
+            StabilizerMode::Average => {
+                lerp(previous, input, 1.0 - 0.95 * self.strength)
+            }
+            StabilizerMode::Rope => {
+                let length = self.strength * MAX_ROPE_LENGTH;
+                let d = distance(previous, input);
+                if d <= length {
+                    return None;
+                }
+                lerp(previous, input, (d - length) / d)
+            }
+        };
+        self.smoothed = Some(next);
+        Some(next)
+    }
+
+    fn interpolate(&mut self, point: PenInput) -> Vec<PenInput> {
+        self.points.push_back(point);
+        match self.points.len() {
+            1 => vec![point],
+            2 => Vec::new(),
+            _ => {
+                if self.points.len() > 4 {
+                    self.points.pop_front();
+                }
+                let p = &self.points;
+                let n = p.len();
+                let p0 = p[n.saturating_sub(4)];
+                catmull_rom(p0, p[n - 3], p[n - 2], p[n - 1]).collect()
+            }
+        }
+    }
+
+    /// Takes a raw input and returns the points to draw next.
+    pub fn push(&mut self, input: PenInput) -> Vec<PenInput> {
+        match self.smooth(input) {
+            Some(p) => self.interpolate(p),
+            None => Vec::new(),
+        }
+    }
+
+    /// Ends the stroke and returns the rest of the points to draw, up to the
+    /// last raw input.
+    pub fn finish(&mut self) -> Vec<PenInput> {
+        let mut rest = Vec::new();
+        if let (Some(raw), Some(smoothed)) = (self.raw, self.smoothed) {
+            if distance(raw, smoothed) > 0.0 {
+                rest = self.interpolate(raw);
+            }
+        }
+        let n = self.points.len();
+        if n >= 2 {
+            let p = &self.points;
+            rest.extend(catmull_rom(
+                p[n.saturating_sub(3)],
+                p[n - 2],
+                p[n - 1],
+                p[n - 1],
+            ));
+        }
+        self.raw = None;
+        self.smoothed = None;
+        self.points.clear();
+        rest
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn input(x: f64, y: f64) -> PenInput {
+        PenInput {
+            x,
+            y,
+            pressure: 1.0,
+        }
+    }
+
+    #[test]
+    fn test_curve_passes_through_inputs() {
+        let mut stabilizer = Stabilizer::new();
+        let inputs = [input(0.0, 0.0), input(10.0, 0.0), input(10.0, 10.0)];
+        let mut points = Vec::new();
+        for i in &inputs {
+            points.extend(stabilizer.push(*i));
+        }
+        points.extend(stabilizer.finish());
+        for i in &inputs {
+            assert!(points.iter().any(|p| distance(*p, *i) < 1e-9));
+        }
+        assert!(points
+            .windows(2)
+            .all(|w| distance(w[0], w[1]) < 2.0 * INTERPOLATION_STEP));
+    }
+
+    #[test]
+    fn test_rope() {
+        let mut stabilizer = Stabilizer::new();
+        stabilizer.mode = StabilizerMode::Rope;
+        stabilizer.strength = 0.5;
+        stabilizer.push(input(0.0, 0.0));
+        assert!(stabilizer.push(input(20.0, 0.0)).is_empty());
+        stabilizer.push(input(40.0, 0.0));
+        assert_eq!(stabilizer.smoothed.unwrap().x, 10.0);
+        let rest = stabilizer.finish();
+        assert_eq!(rest.last().unwrap().x, 40.0);
+    }
+}
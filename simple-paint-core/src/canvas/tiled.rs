@@ -0,0 +1,329 @@
+use std::rc::Rc;
+
+use super::*;
+
+/// Width and height of a tile in pixels.
+pub const TILE_SIZE: usize = 64;
+
+/// An image split into tiles of `TILE_SIZE` x `TILE_SIZE` pixels. A tile is
+/// only allocated once one of its pixels is set to something other than the
+/// fill color, and clones share tiles until one of them changes.
+#[derive(Debug, Clone)]
+pub struct TiledImage {
+    pub width: usize,
+    pub height: usize,
+    fill: RGB,
+    columns: usize,
+    /// Pixels of each tile in the layout of `SingleVecImage::vector`, row
+    /// by row.
+    tiles: Vec<Option<Rc<Vec<u8>>>>,
+}
+
+impl TiledImage {
+    pub fn new(width: usize, height: usize, fill: RGB) -> TiledImage {
+        let columns = width.div_ceil(TILE_SIZE);
+        TiledImage {
+            width,
+            height,
+            fill,
+            columns,
+            tiles: vec![None; columns * height.div_ceil(TILE_SIZE)],
+        }
+    }
+
+    /// Copies `image`, taking the top left pixel as the fill color as it is
+    /// usually the background.
+    pub fn from_image(image: &SingleVecImage) -> TiledImage {
+        let fill = if image.width > 0 && image.height > 0 {
+            image.get(0, 0)
+        } else {
+            RGB::TRANSPARENT
+        };
+        let mut tiled = TiledImage::new(image.width, image.height, fill);
+        for y in 0..image.height {
+            for x in 0..image.width {
+                tiled.set(x, y, image.get(x, y));
+            }
+        }
+        tiled
+    }
+
+    pub fn to_image(&self) -> SingleVecImage {
+        let mut image = SingleVecImage {
+            width: self.width,
+            height: self.height,
+            vector: vec![0; 4 * self.width * self.height],
+        };
+        self.copy_to(
+            0..self.width,
+            0..self.height,
+            &mut image.vector,
+            4 * self.width,
+        );
+        image
+    }
+
+    /// Copies the pixels in `xs` x `ys` to `data`, which holds rows of
+    /// `stride` bytes in the layout of `SingleVecImage::vector` starting at
+    /// the top left of the image.
+    pub fn copy_to(
+        &self,
+        xs: std::ops::Range<usize>,
+        ys: std::ops::Range<usize>,
+        data: &mut [u8],
+        stride: usize,
+    ) {
+        let fill = self.fill.array.repeat(TILE_SIZE);
+        for y in ys {
+            let mut x = xs.start;
+            while x < xs.end {
+                // The part of the row within one tile.
+                let end = ((x / TILE_SIZE + 1) * TILE_SIZE).min(xs.end);
+                let to = y * stride + 4 * x;
+                let to = &mut data[to..to + 4 * (end - x)];
+                let (t, i) = self.locate(x, y);
+                match &self.tiles[t] {
+                    Some(tile) => to.copy_from_slice(&tile[i..i + to.len()]),
+                    None => to.copy_from_slice(&fill[..to.len()]),
+                }
+                x = end;
+            }
+        }
+    }
+
+    pub fn fill(&self) -> RGB {
+        self.fill
+    }
+
+    /// Returns the index of the tile containing (`x`, `y`) and the index of
+    /// the pixel in it.
+    fn locate(&self, x: usize, y: usize) -> (usize, usize) {
+        let tile = y / TILE_SIZE * self.columns + x / TILE_SIZE;
+        (tile, 4 * (y % TILE_SIZE * TILE_SIZE + x % TILE_SIZE))
+    }
+
+    pub fn get(&self, x: usize, y: usize) -> RGB {
+        let (t, i) = self.locate(x, y);
+        match &self.tiles[t] {
+            Some(tile) => {
+                let mut array = [0; 4];
+                array.clone_from_slice(&tile[i..i + 4]);
+                RGB { array }
+            }
+            None => self.fill,
+        }
+    }
+
+    pub fn set(&mut self, x: usize, y: usize, color: RGB) {
+        let (t, i) = self.locate(x, y);
+        match &mut self.tiles[t] {
+            Some(tile) => {
+                Rc::make_mut(tile)[i..i + 4].clone_from_slice(&color.array)
+            }
+            None if color == self.fill => (),
+            None => {
+                let mut tile = self.fill.array.repeat(TILE_SIZE * TILE_SIZE);
+                tile[i..i + 4].clone_from_slice(&color.array);
+                self.tiles[t] = Some(Rc::new(tile));
+            }
+        }
+    }
+
+    /// Whether the tile containing (`x`, `y`) only has the fill color.
+    pub fn is_blank(&self, x: usize, y: usize) -> bool {
+        self.tiles[self.locate(x, y).0].is_none()
+    }
+
+    /// Sets the whole tile containing (`x`, `y`) to the fill color.
+    pub fn clear_tile(&mut self, x: usize, y: usize) {
+        let (t, _) = self.locate(x, y);
+        self.tiles[t] = None;
+    }
+
+    /// The smallest area covering the allocated tiles, clipped to the image.
+    /// Returns `None` if the whole image has the fill color.
+    pub fn tile_bounds(
+        &self,
+    ) -> Option<(std::ops::Range<usize>, std::ops::Range<usize>)> {
+        let (mut x0, mut y0) = (usize::MAX, usize::MAX);
+        let (mut x1, mut y1) = (0, 0);
+        for (t, _) in self.tiles.iter().enumerate().filter(|(_, t)| t.is_some())
+        {
+            let (x, y) = (t % self.columns, t / self.columns);
+            x0 = x0.min(x);
+            y0 = y0.min(y);
+            x1 = x1.max(x + 1);
+            y1 = y1.max(y + 1);
+        }
+        if x0 >= x1 {
+            return None;
+        }
+        Some((
+            x0 * TILE_SIZE..(x1 * TILE_SIZE).min(self.width),
+            y0 * TILE_SIZE..(y1 * TILE_SIZE).min(self.height),
+        ))
+    }
+
+    /// Bytes used by the allocated tiles, counting shared ones fully.
+    pub fn memory_size(&self) -> usize {
+        self.tiles.iter().flatten().map(|t| t.len()).sum()
+    }
+
+    /// Bytes used by the allocated tiles no other image shares.
+    pub fn unshared_memory_size(&self) -> usize {
+        self.tiles
+            .iter()
+            .flatten()
+            .filter(|t| Rc::strong_count(t) == 1)
+            .map(|t| t.len())
+            .sum()
+    }
+
+    /// Averages the pixels within `r` of (`x`, `y`), ignoring those outside
+    /// of the image. Returns `None` if there are none.
+    pub fn average(&self, x: i64, y: i64, r: i64) -> Option<RGB> {
+        let mut sum = [0; 4];
+        let mut n = 0;
+        for y in (y - r).max(0)..=(y + r).min(self.height as i64 - 1) {
+            for x in (x - r).max(0)..=(x + r).min(self.width as i64 - 1) {
+                let c = self.get(x as usize, y as usize);
+                for (s, v) in sum.iter_mut().zip(&c.array) {
+                    *s += *v as u32;
+                }
+                n += 1;
+            }
+        }
+        if n == 0 {
+            return None;
+        }
+        let mut array = [0; 4];
+        for (a, s) in array.iter_mut().zip(&sum) {
+            *a = ((s + n / 2) / n) as u8;
+        }
+        Some(RGB { array })
+    }
+
+    /// Changes the size to `width` x `height`, putting the old pixels at
+    /// `offset` and filling the rest with `background`. Pixels outside of
+    /// the new size are cut off. Tiles are moved without copying if the
+    /// offset is a multiple of `TILE_SIZE`.
+    pub fn resize(
+        &mut self,
+        width: usize,
+        height: usize,
+        offset: (i64, i64),
+        background: RGB,
+    ) {
+        let mut resized = TiledImage::new(width, height, background);
+        let size = TILE_SIZE as i64;
+        let aligned = offset.0 % size == 0 && offset.1 % size == 0;
+        for (t, tile) in self.tiles.iter().enumerate() {
+            if tile.is_none() && self.fill == background {
+                continue;
+            }
+            let (x0, y0) =
+                (t % self.columns * TILE_SIZE, t / self.columns * TILE_SIZE);
+            let (x1, y1) = (
+                (x0 + TILE_SIZE).min(self.width),
+                (y0 + TILE_SIZE).min(self.height),
+            );
+            let (tx, ty) = (x0 as i64 + offset.0, y0 as i64 + offset.1);
+            // Only whole tiles are moved, as the rest of a tile at the edge
+            // holds the old fill color.
+            let whole = x1 - x0 == TILE_SIZE && y1 - y0 == TILE_SIZE;
+            if aligned
+                && whole
+                && 0 <= tx
+                && 0 <= ty
+                && tx + size <= width as i64
+                && ty + size <= height as i64
+            {
+                let (target, _) = resized.locate(tx as usize, ty as usize);
+                resized.tiles[target] = tile.clone().or_else(|| {
+                    Some(Rc::new(self.fill.array.repeat(TILE_SIZE * TILE_SIZE)))
+                });
+                continue;
+            }
+            for y in y0..y1 {
+                let ty = y as i64 + offset.1;
+                if ty < 0 || ty >= height as i64 {
+                    continue;
+                }
+                for x in x0..x1 {
+                    let tx = x as i64 + offset.0;
+                    if 0 <= tx && tx < width as i64 {
+                        resized.set(tx as usize, ty as usize, self.get(x, y));
+                    }
+                }
+            }
+        }
+        *self = resized;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const WHITE: RGB = RGB::new(0xff, 0xff, 0xff);
+    const BLACK: RGB = RGB::new(0, 0, 0);
+
+    #[test]
+    fn test_lazy_tiles() {
+        let mut image = TiledImage::new(200, 100, WHITE);
+        assert_eq!(image.memory_size(), 0);
+        image.set(70, 10, WHITE);
+        assert_eq!(image.memory_size(), 0);
+        image.set(70, 10, BLACK);
+        assert_eq!(image.memory_size(), 4 * TILE_SIZE * TILE_SIZE);
+        assert!(!image.is_blank(64, 0) && image.is_blank(0, 0));
+        let copy = image.clone();
+        image.set(71, 10, BLACK);
+        assert_eq!(copy.get(71, 10), WHITE);
+        assert_eq!(image.get(71, 10), BLACK);
+        assert_eq!(image.to_image().get(70, 10), BLACK);
+        let round_trip = TiledImage::from_image(&image.to_image());
+        assert_eq!(round_trip.memory_size(), 4 * TILE_SIZE * TILE_SIZE);
+    }
+
+    #[test]
+    fn test_average() {
+        let mut image = TiledImage::new(4, 4, WHITE);
+        image.set(0, 0, BLACK);
+        assert_eq!(image.average(0, 0, 0), Some(BLACK));
+        assert_eq!(image.average(0, 0, 1), Some(RGB::new(0xbf, 0xbf, 0xbf)));
+        assert_eq!(image.average(-5, 0, 1), None);
+    }
+
+    #[test]
+    fn test_resize() {
+        let mut image = TiledImage::new(3, 2, WHITE);
+        image.set(0, 0, BLACK);
+        image.resize(4, 4, (1, 2), RGB::TRANSPARENT);
+        assert_eq!((image.width, image.height), (4, 4));
+        assert_eq!(image.get(1, 2), BLACK);
+        assert_eq!(image.get(3, 3), WHITE);
+        assert_eq!(image.get(0, 2), RGB::TRANSPARENT);
+        assert_eq!(image.get(1, 1), RGB::TRANSPARENT);
+        image.resize(2, 2, (-2, -2), RGB::TRANSPARENT);
+        assert_eq!(image.get(0, 0), WHITE);
+        assert_eq!(image.get(1, 1), WHITE);
+    }
+
+    #[test]
+    fn test_resize_by_tiles() {
+        let mut image = TiledImage::new(200, 200, RGB::TRANSPARENT);
+        image.set(10, 10, BLACK);
+        image.set(190, 10, BLACK);
+        let size = TILE_SIZE as i64;
+        image.resize(200 + TILE_SIZE, 200, (size, 0), WHITE);
+        assert_eq!(image.get(10 + TILE_SIZE, 10), BLACK);
+        assert_eq!(image.get(190 + TILE_SIZE, 10), BLACK);
+        assert_eq!(image.get(11 + TILE_SIZE, 10), RGB::TRANSPARENT);
+        assert_eq!(image.get(10, 10), WHITE);
+        assert_eq!(image.get(199 + TILE_SIZE, 199), RGB::TRANSPARENT);
+        image.resize(100, 100, (-size, 0), WHITE);
+        image.resize(200, 100, (0, 0), WHITE);
+        assert_eq!(image.get(150, 10), WHITE);
+    }
+}
@@ -0,0 +1,306 @@
+use super::*;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Filter {
+    Nearest,
+    Bilinear,
+}
+
+/// Where floating pixels are put, relative to where they were lifted from.
+/// The scale and the rotation are around the center of the pixels.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Placement {
+    pub offset: (f64, f64),
+    /// Negative values flip the pixels.
+    pub scale: (f64, f64),
+    /// Clockwise rotation in radians.
+    pub angle: f64,
+}
+
+impl Placement {
+    pub const IDENTITY: Placement = Placement {
+        offset: (0.0, 0.0),
+        scale: (1.0, 1.0),
+        angle: 0.0,
+    };
+}
+
+/// Pixels lifted from a layer to be transformed.
+#[derive(Debug, Clone)]
+pub struct Floating {
+    pub image: SingleVecImage,
+    /// Position on the canvas the pixels were lifted from.
+    pub origin: (usize, usize),
+    pub placement: Placement,
+}
+
+impl Floating {
+    /// Returns the center of the placed pixels in canvas coordinates.
+    pub fn center(&self) -> (f64, f64) {
+        (
+            self.origin.0 as f64
+                + self.image.width as f64 / 2.0
+                + self.placement.offset.0,
+            self.origin.1 as f64
+                + self.image.height as f64 / 2.0
+                + self.placement.offset.1,
+        )
+    }
+
+    /// Maps a point of `image` to the canvas.
+    pub fn to_canvas(&self, (x, y): (f64, f64)) -> (f64, f64) {
+        let Placement { scale, angle, .. } = self.placement;
+        let x = (x - self.image.width as f64 / 2.0) * scale.0;
+        let y = (y - self.image.height as f64 / 2.0) * scale.1;
+        let (sin, cos) = angle.sin_cos();
+        let (cx, cy) = self.center();
+        (cx + x * cos - y * sin, cy + x * sin + y * cos)
+    }
+
+    fn to_image(&self, (x, y): (f64, f64)) -> (f64, f64) {
+        let Placement { scale, angle, .. } = self.placement;
+        let (cx, cy) = self.center();
+        let (x, y) = (x - cx, y - cy);
+        let (sin, cos) = angle.sin_cos();
+        let (x, y) = (x * cos + y * sin, -x * sin + y * cos);
+        (
+            x / scale.0 + self.image.width as f64 / 2.0,
+            y / scale.1 + self.image.height as f64 / 2.0,
+        )
+    }
+
+    /// Corners of the placed pixels in canvas coordinates, clockwise from the
+    /// top left one before the transformation.
+    pub fn corners(&self) -> [(f64, f64); 4] {
+        let (w, h) = (self.image.width as f64, self.image.height as f64);
+        [
+            self.to_canvas((0.0, 0.0)),
+            self.to_canvas((w, 0.0)),
+            self.to_canvas((w, h)),
+            self.to_canvas((0.0, h)),
+        ]
+    }
+
+    fn pixel(&self, x: i64, y: i64) -> RGB {
+        if 0 <= x
+            && 0 <= y
+            && (x as usize) < self.image.width
+            && (y as usize) < self.image.height
+        {
+            self.image.get(x as usize, y as usize)
+        } else {
+            RGB::TRANSPARENT
+        }
+    }
+
+    /// Samples `image` at (`x`, `y`), where pixel centers are at half
+    /// integers.
+    fn sample(&self, x: f64, y: f64, filter: Filter) -> RGB {
+        match filter {
+            Filter::Nearest => self.pixel(x.floor() as i64, y.floor() as i64),
+            Filter::Bilinear => {
+                let (x, y) = (x - 0.5, y - 0.5);
+                let (x0, y0) = (x.floor(), y.floor());
+                let (fx, fy) = (x - x0, y - y0);
+                let (x0, y0) = (x0 as i64, y0 as i64);
+                let top = self.pixel(x0, y0).mix(self.pixel(x0 + 1, y0), fx);
+                let bottom =
+                    self.pixel(x0, y0 + 1).mix(self.pixel(x0 + 1, y0 + 1), fx);
+                top.mix(bottom, fy)
+            }
+        }
+    }
+
+    /// Draws the placed pixels over `target` and returns the changed area.
+    pub fn stamp(
+        &self,
+        target: &mut TiledImage,
+        filter: Filter,
+    ) -> Option<Rectangle> {
+        let corners = self.corners();
+        let min = |f: fn(&(f64, f64)) -> f64| {
+            corners.iter().map(f).fold(f64::INFINITY, f64::min).floor()
+        };
+        let max = |f: fn(&(f64, f64)) -> f64| {
+            corners
+                .iter()
+                .map(f)
+                .fold(f64::NEG_INFINITY, f64::max)
+                .ceil()
+        };
+        let x0 = min(|c| c.0).max(0.0) as usize;
+        let y0 = min(|c| c.1).max(0.0) as usize;
+        let x1 = (max(|c| c.0).max(0.0) as usize).min(target.width);
+        let y1 = (max(|c| c.1).max(0.0) as usize).min(target.height);
+        if x0 >= x1 || y0 >= y1 {
+            return None;
+        }
+        for y in y0..y1 {
+            for x in x0..x1 {
+                let (sx, sy) = self.to_image((x as f64 + 0.5, y as f64 + 0.5));
+                let c = self.sample(sx, sy, filter);
+                if c != RGB::TRANSPARENT {
+                    target.set(x, y, target.get(x, y).over(c, 1.0));
+                }
+            }
+        }
+        Some(Rectangle {
+            x: x0 as f64,
+            y: y0 as f64,
+            width: (x1 - x0) as f64,
+            height: (y1 - y0) as f64,
+        })
+    }
+}
+
+/// Moves the selected pixels of `image`, or all of them, into a floating
+/// image and leaves them transparent in `image`. Without a selection, only
+/// the tiles that were drawn on are lifted. Returns `None` if no pixels are
+/// selected or there is nothing to lift.
+pub fn lift(
+    image: &mut TiledImage,
+    selection: Option<&Selection>,
+) -> Option<Floating> {
+    let selected = |x, y| selection.is_none_or(|s| s.contains(x, y));
+    let (x0, x1, y0, y1) = match selection {
+        Some(selection) => selection_bounds(selection, image)?,
+        None if image.fill() != RGB::TRANSPARENT => {
+            (0, image.width, 0, image.height)
+        }
+        None => {
+            let (xs, ys) = image.tile_bounds()?;
+            (xs.start, xs.end, ys.start, ys.end)
+        }
+    };
+    let mut floating = SingleVecImage::new(
+        std::iter::repeat(RGB::TRANSPARENT),
+        x1 - x0,
+        y1 - y0,
+    );
+    for y in y0..y1 {
+        for x in x0..x1 {
+            if selected(x, y) {
+                floating.set(x - x0, y - y0, image.get(x, y));
+                image.set(x, y, RGB::TRANSPARENT);
+            }
+        }
+    }
+    Some(Floating {
+        image: floating,
+        origin: (x0, y0),
+        placement: Placement::IDENTITY,
+    })
+}
+
+/// Returns the smallest area containing the selected pixels of `image`, as
+/// `(x0, x1, y0, y1)`.
+fn selection_bounds(
+    selection: &Selection,
+    image: &TiledImage,
+) -> Option<(usize, usize, usize, usize)> {
+    let (mut x0, mut y0) = (usize::MAX, usize::MAX);
+    let (mut x1, mut y1) = (0, 0);
+    for y in 0..image.height {
+        for x in 0..image.width {
+            if selection.contains(x, y) {
+                x0 = x0.min(x);
+                y0 = y0.min(y);
+                x1 = x1.max(x + 1);
+                y1 = y1.max(y + 1);
+            }
+        }
+    }
+    if x0 >= x1 {
+        return None;
+    }
+    Some((x0, x1, y0, y1))
+}
+
+/// The smallest rectangle containing both.
+pub fn union(a: &Rectangle, b: &Rectangle) -> Rectangle {
+    let x = a.x.min(b.x);
+    let y = a.y.min(b.y);
+    Rectangle {
+        x,
+        y,
+        width: (a.x + a.width).max(b.x + b.width) - x,
+        height: (a.y + a.height).max(b.y + b.height) - y,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const BLACK: RGB = RGB::new(0, 0, 0);
+    const WHITE: RGB = RGB::new(0xff, 0xff, 0xff);
+
+    fn image() -> TiledImage {
+        let mut image = TiledImage::new(6, 4, RGB::TRANSPARENT);
+        image.set(1, 1, BLACK);
+        image.set(2, 1, WHITE);
+        image
+    }
+
+    fn square(x0: f64, y0: f64, x1: f64, y1: f64) -> Selection {
+        Selection::from_polygon(&[(x0, y0), (x1, y0), (x1, y1), (x0, y1)], 6, 4)
+    }
+
+    #[test]
+    fn test_lift_and_move() {
+        let mut image = image();
+        let mut floating = lift(&mut image, None).unwrap();
+        assert_eq!(image.get(1, 1), RGB::TRANSPARENT);
+        assert_eq!(floating.image.width, 6);
+        floating.placement.offset = (2.0, 1.0);
+        floating.stamp(&mut image, Filter::Nearest);
+        assert_eq!(image.get(3, 2), BLACK);
+        assert_eq!(image.get(4, 2), WHITE);
+        assert_eq!(image.get(1, 1), RGB::TRANSPARENT);
+    }
+
+    #[test]
+    fn test_lift_drawn_tiles() {
+        let mut image = TiledImage::new(1000, 800, RGB::TRANSPARENT);
+        assert!(lift(&mut image, None).is_none());
+        image.set(200, 700, BLACK);
+        let floating = lift(&mut image, None).unwrap();
+        assert_eq!(floating.origin, (192, 640));
+        assert_eq!((floating.image.width, floating.image.height), (64, 64));
+        assert_eq!(floating.image.get(8, 60), BLACK);
+        let mut background = TiledImage::new(100, 80, WHITE);
+        let floating = lift(&mut background, None).unwrap();
+        assert_eq!((floating.image.width, floating.image.height), (100, 80));
+    }
+
+    #[test]
+    fn test_flip() {
+        let mut image = image();
+        let selection = square(1.0, 1.0, 3.0, 2.0);
+        let mut floating = lift(&mut image, Some(&selection)).unwrap();
+        assert_eq!((floating.image.width, floating.image.height), (2, 1));
+        floating.placement.scale = (-1.0, 1.0);
+        let area = floating.stamp(&mut image, Filter::Nearest).unwrap();
+        assert_eq!(
+            (area.x, area.y, area.width, area.height),
+            (1.0, 1.0, 2.0, 1.0)
+        );
+        assert_eq!(image.get(1, 1), WHITE);
+        assert_eq!(image.get(2, 1), BLACK);
+    }
+
+    #[test]
+    fn test_rotate() {
+        let mut image = image();
+        let selection = square(1.0, 1.0, 3.0, 3.0);
+        let mut floating = lift(&mut image, Some(&selection)).unwrap();
+        floating.placement.angle = std::f64::consts::FRAC_PI_2;
+        floating.stamp(&mut image, Filter::Bilinear);
+        assert_eq!(image.get(2, 1), BLACK);
+        assert_eq!(image.get(2, 2), WHITE);
+        assert_eq!(image.get(1, 1), RGB::TRANSPARENT);
+        let corners = floating.corners();
+        assert!((corners[0].0 - 3.0).abs() < 1e-9);
+        assert!((corners[0].1 - 1.0).abs() < 1e-9);
+    }
+}
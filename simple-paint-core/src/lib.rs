@@ -0,0 +1,8 @@
+//! The painting engine of Simple Paint, free of any GTK dependency so that
+//! it can be used by batch tools and tests. The GTK application is the
+//! `simple-paint` package at the root of the workspace.
+
+pub mod canvas;
+pub mod png;
+pub mod session;
+pub mod strokes;
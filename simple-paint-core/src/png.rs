@@ -0,0 +1,128 @@
+//! Reading and writing PNG files with the `png` crate, for tools that run
+//! without GTK.
+
+use std::io;
+
+use ::png::{
+    AdaptiveFilterType, BitDepth, ColorType, Decoder, DecodingError, Encoder,
+    EncodingError, Transformations,
+};
+
+use crate::canvas::{SingleVecImage, RGB};
+
+/// Writes `image` as an 8-bit RGBA PNG.
+pub fn encode(
+    image: &SingleVecImage,
+    out: impl io::Write,
+) -> Result<(), EncodingError> {
+    let mut encoder =
+        Encoder::new(out, image.width as u32, image.height as u32);
+    encoder.set_color(ColorType::Rgba);
+    encoder.set_depth(BitDepth::Eight);
+    encoder.set_adaptive_filter(AdaptiveFilterType::Adaptive);
+    let mut writer = encoder.write_header()?;
+    let mut data = Vec::with_capacity(4 * image.width * image.height);
+    for y in 0..image.height {
+        for x in 0..image.width {
+            let (r, g, b, a) = image.get(x, y).unpremultiplied();
+            data.extend_from_slice(&[r, g, b, a]);
+        }
+    }
+    writer.write_image_data(&data)?;
+    writer.finish()
+}
+
+/// Reads a PNG of any color type and depth. Images too large to decode in
+/// the memory limit of the `png` crate are rejected.
+pub fn decode(input: impl io::Read) -> Result<SingleVecImage, DecodingError> {
+    let mut decoder = Decoder::new(input);
+    // Palettes and bit depths are expanded to 8-bit gray or RGB, with alpha.
+    decoder.set_transformations(
+        Transformations::normalize_to_color8() | Transformations::ALPHA,
+    );
+    let mut reader = decoder.read_info()?;
+    let mut buffer = vec![0; reader.output_buffer_size()];
+    let info = reader.next_frame(&mut buffer)?;
+    let channels = info.color_type.samples();
+    let width = info.width as usize;
+    let pixels = buffer
+        .chunks_exact(info.line_size)
+        .take(info.height as usize)
+        .flat_map(|row| row[..width * channels].chunks_exact(channels))
+        .map(|p| match *p {
+            [l, a] => RGB::with_alpha(l, l, l, a),
+            [r, g, b, a] => RGB::with_alpha(r, g, b, a),
+            _ => unreachable!("alpha is always added"),
+        });
+    Ok(SingleVecImage::new(pixels, width, info.height as usize))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_round_trip() {
+        let mut image =
+            SingleVecImage::new(std::iter::repeat(RGB::TRANSPARENT), 300, 300);
+        image.set(0, 0, RGB::new(0xff, 0, 0));
+        image.set(299, 299, RGB::with_alpha(0, 0xff, 0, 0x80));
+        let mut file = Vec::new();
+        encode(&image, &mut file).unwrap();
+        let decoded = decode(&file[..]).unwrap();
+        assert_eq!((decoded.width, decoded.height), (300, 300));
+        assert_eq!(decoded.vector, image.vector);
+    }
+
+    #[test]
+    fn test_encode_compresses() {
+        let image = SingleVecImage::new(
+            std::iter::repeat(RGB::new(0xff, 0xff, 0xff)),
+            2000,
+            1000,
+        );
+        let mut file = Vec::new();
+        encode(&image, &mut file).unwrap();
+        assert!(file.len() < 100_000, "{} bytes", file.len());
+    }
+
+    #[test]
+    fn test_decode_compressed() {
+        let file = include_bytes!("../../img/Screenshot_20210401_180438.png");
+        let image = decode(&file[..]).unwrap();
+        assert_eq!((image.width, image.height), (1651, 960));
+        assert_eq!(image.get(0, 0), RGB::TRANSPARENT);
+        assert_eq!(image.get(800, 500), RGB::new(0xff, 0xff, 0xff));
+        assert_eq!(image.get(300, 100), RGB::new(51, 51, 64));
+        assert_eq!(image.get(1650, 959), RGB::TRANSPARENT);
+    }
+
+    #[test]
+    fn test_decode_broken() {
+        let image =
+            SingleVecImage::new(std::iter::repeat(RGB::TRANSPARENT), 4, 4);
+        let mut file = Vec::new();
+        encode(&image, &mut file).unwrap();
+        // Changes the height without updating the checksum of the header.
+        let mut corrupted = file.clone();
+        corrupted[20..24].copy_from_slice(&5u32.to_be_bytes());
+        assert!(decode(&corrupted[..]).is_err());
+        assert!(decode(&file[..file.len() / 2]).is_err());
+    }
+
+    #[test]
+    fn test_decode_huge() {
+        // A header claiming about 16 EB of pixels, with a valid checksum.
+        let mut file = Vec::new();
+        let encoder = Encoder::new(&mut file, 0x7fff_ffff, 0x7fff_ffff);
+        let mut writer = encoder.write_header().unwrap();
+        // An empty zlib stream.
+        let data = [0x78, 0x01, 0x03, 0x00, 0x00, 0x00, 0x00, 0x01];
+        writer.write_chunk(::png::chunk::IDAT, &data).unwrap();
+        drop(writer);
+        assert!(matches!(
+            decode(&file[..]),
+            Err(DecodingError::LimitsExceeded)
+        ));
+    }
+}
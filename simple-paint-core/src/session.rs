@@ -0,0 +1,451 @@
+//! Recording of the input the application receives, so that a session can
+//! be replayed to reproduce a problem.
+//!
+//! Each line holds the seconds since the recording started and an event.
+//! Positions are in viewport coordinates and keys are GDK key values. The
+//! view and the settings of the tools are recorded as they change, so that
+//! the positions land on the same canvas pixels when replayed:
+//!
+//! ```text
+//! 0.000 resize 1000 760
+//! 0.002 center
+//! 0.800 zoom 500 380 1.2
+//! 0.950 pen-size 8
+//! 1.204 pen 320.5 200 0.7
+//! 1.220 pen 324 203.5 0.7
+//! 1.301 end
+//! 2.010 key-press 65507
+//! 2.300 key-release 65507
+//! ```
+//!
+//! Opened files are recorded by path and read again when replayed.
+
+use std::{
+    fmt,
+    fs::File,
+    io::{self, LineWriter, Write},
+    path::{Path, PathBuf},
+    str::FromStr,
+    time::Instant,
+};
+
+use crate::canvas::{
+    FillSetting, Filter, PenInput, PressureCurve, Resample, StabilizerMode, RGB,
+};
+use crate::strokes::{format_color, parse_color, ParseError};
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Event {
+    /// The pen moved while touching the viewport.
+    Pen(PenInput),
+    /// The pen was lifted.
+    StrokeEnd,
+    /// Whether the pen is used with its eraser tip.
+    EraserTip(bool),
+    KeyPress(u32),
+    KeyRelease(u32),
+    /// All keys were released as the viewport lost the focus.
+    ReleaseAllKeys,
+    /// The viewport got the width and height.
+    Resize(usize, usize),
+    /// The canvas was centered in the viewport.
+    CenterCanvas,
+    /// The view was zoomed by the factor around the position.
+    Zoom((f64, f64), f64),
+    /// The canvas was moved by the distance in viewport coordinates.
+    Scroll(f64, f64),
+    PenSize(f64),
+    PenOpacity(f64),
+    Antialias(bool),
+    InfiniteCanvas(bool),
+    TransformFilter(Filter),
+    Pressure(PressureCurve),
+    /// The width of the sampled square and whether all layers are sampled.
+    Eyedropper(usize, bool),
+    Fill(FillSetting),
+    StabilizerMode(StabilizerMode),
+    StabilizerStrength(f64),
+    /// The foreground and background colors.
+    PenColors(RGB, RGB),
+    SwapPenColors,
+    ActiveLayer(usize),
+    AddLayer,
+    DuplicateLayer,
+    DeleteLayer,
+    /// The active layer was moved up if true, down otherwise.
+    MoveLayer(bool),
+    LayerVisible(usize, bool),
+    LayerOpacity(usize, f64),
+    NewImage((usize, usize), RGB),
+    /// The canvas got the size, with the layers placed by the anchor.
+    ResizeCanvas((usize, usize), (f64, f64)),
+    ScaleImage((usize, usize), Resample),
+    Open(PathBuf),
+}
+
+const SWITCHES: [(bool, &str); 2] = [(true, "on"), (false, "off")];
+const DIRECTIONS: [(bool, &str); 2] = [(true, "up"), (false, "down")];
+const FILTERS: [(Filter, &str); 2] =
+    [(Filter::Nearest, "nearest"), (Filter::Bilinear, "bilinear")];
+const RESAMPLES: [(Resample, &str); 4] = [
+    (Resample::Nearest, "nearest"),
+    (Resample::Bilinear, "bilinear"),
+    (Resample::Bicubic, "bicubic"),
+    (Resample::Area, "area"),
+];
+const STABILIZER_MODES: [(StabilizerMode, &str); 2] = [
+    (StabilizerMode::Average, "average"),
+    (StabilizerMode::Rope, "rope"),
+];
+
+/// Returns the name of `value` in `names`.
+fn name<T: PartialEq>(names: &[(T, &'static str)], value: T) -> &'static str {
+    names.iter().find(|(v, _)| *v == value).unwrap().1
+}
+
+impl fmt::Display for Event {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let on_off = |b: bool| name(&SWITCHES, b);
+        match self {
+            Event::Pen(p) => write!(f, "pen {} {} {}", p.x, p.y, p.pressure),
+            Event::StrokeEnd => write!(f, "end"),
+            Event::EraserTip(e) => write!(f, "eraser-tip {}", on_off(*e)),
+            Event::KeyPress(k) => write!(f, "key-press {}", k),
+            Event::KeyRelease(k) => write!(f, "key-release {}", k),
+            Event::ReleaseAllKeys => write!(f, "release-all-keys"),
+            Event::Resize(w, h) => write!(f, "resize {} {}", w, h),
+            Event::CenterCanvas => write!(f, "center"),
+            Event::Zoom((x, y), ds) => write!(f, "zoom {} {} {}", x, y, ds),
+            Event::Scroll(dx, dy) => write!(f, "scroll {} {}", dx, dy),
+            Event::PenSize(s) => write!(f, "pen-size {}", s),
+            Event::PenOpacity(o) => write!(f, "pen-opacity {}", o),
+            Event::Antialias(a) => write!(f, "antialias {}", on_off(*a)),
+            Event::InfiniteCanvas(i) => {
+                write!(f, "infinite-canvas {}", on_off(*i))
+            }
+            Event::TransformFilter(t) => {
+                write!(f, "transform-filter {}", name(&FILTERS, *t))
+            }
+            Event::Pressure(p) => write!(
+                f,
+                "pressure {} {} {}",
+                p.gamma,
+                p.min_size,
+                on_off(p.opacity)
+            ),
+            Event::Eyedropper(size, merged) => {
+                write!(f, "eyedropper {} {}", size, on_off(*merged))
+            }
+            Event::Fill(s) => write!(
+                f,
+                "fill {} {} {} {} {}",
+                s.tolerance,
+                on_off(s.contiguous),
+                on_off(s.sample_all_layers),
+                s.expand,
+                s.close_gaps
+            ),
+            Event::StabilizerMode(m) => {
+                write!(f, "stabilizer-mode {}", name(&STABILIZER_MODES, *m))
+            }
+            Event::StabilizerStrength(s) => {
+                write!(f, "stabilizer-strength {}", s)
+            }
+            Event::PenColors(foreground, background) => write!(
+                f,
+                "colors {} {}",
+                format_color(*foreground),
+                format_color(*background)
+            ),
+            Event::SwapPenColors => write!(f, "swap-colors"),
+            Event::ActiveLayer(i) => write!(f, "active-layer {}", i),
+            Event::AddLayer => write!(f, "add-layer"),
+            Event::DuplicateLayer => write!(f, "duplicate-layer"),
+            Event::DeleteLayer => write!(f, "delete-layer"),
+            Event::MoveLayer(up) => {
+                write!(f, "move-layer {}", name(&DIRECTIONS, *up))
+            }
+            Event::LayerVisible(i, v) => {
+                write!(f, "layer-visible {} {}", i, on_off(*v))
+            }
+            Event::LayerOpacity(i, o) => write!(f, "layer-opacity {} {}", i, o),
+            Event::NewImage((w, h), background) => {
+                write!(f, "new-image {} {} {}", w, h, format_color(*background))
+            }
+            Event::ResizeCanvas((w, h), (x, y)) => {
+                write!(f, "resize-canvas {} {} {} {}", w, h, x, y)
+            }
+            Event::ScaleImage((w, h), method) => write!(
+                f,
+                "scale-image {} {} {}",
+                w,
+                h,
+                name(&RESAMPLES, *method)
+            ),
+            Event::Open(path) => write!(f, "open {}", path.display()),
+        }
+    }
+}
+
+fn parse_arg<T: FromStr>(s: Option<&str>) -> Result<T, String> {
+    let s = s.ok_or("missing argument")?;
+    s.parse().map_err(|_| format!("invalid argument `{}`", s))
+}
+
+/// Parses one of the names in `names`.
+fn parse_name<T: Copy>(
+    names: &[(T, &str)],
+    s: Option<&str>,
+) -> Result<T, String> {
+    let s = s.ok_or("missing argument")?;
+    names
+        .iter()
+        .find(|(_, n)| *n == s)
+        .map(|(v, _)| *v)
+        .ok_or_else(|| {
+            let names: Vec<_> = names.iter().map(|(_, n)| *n).collect();
+            format!("expected one of {}, found `{}`", names.join(", "), s)
+        })
+}
+
+impl FromStr for Event {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Event, String> {
+        // The path is the rest of the line, spaces included.
+        if let Some(path) = s.strip_prefix("open ") {
+            return Ok(Event::Open(PathBuf::from(path.trim())));
+        }
+        let mut words = s.split_whitespace();
+        let name = words.next().ok_or("empty event")?;
+        let mut arg = || words.next();
+        let event = match name {
+            "pen" => Event::Pen(PenInput {
+                x: parse_arg(arg())?,
+                y: parse_arg(arg())?,
+                pressure: parse_arg(arg())?,
+            }),
+            "end" => Event::StrokeEnd,
+            "eraser-tip" => Event::EraserTip(parse_name(&SWITCHES, arg())?),
+            "key-press" => Event::KeyPress(parse_arg(arg())?),
+            "key-release" => Event::KeyRelease(parse_arg(arg())?),
+            "release-all-keys" => Event::ReleaseAllKeys,
+            "resize" => Event::Resize(parse_arg(arg())?, parse_arg(arg())?),
+            "center" => Event::CenterCanvas,
+            "zoom" => Event::Zoom(
+                (parse_arg(arg())?, parse_arg(arg())?),
+                parse_arg(arg())?,
+            ),
+            "scroll" => Event::Scroll(parse_arg(arg())?, parse_arg(arg())?),
+            "pen-size" => Event::PenSize(parse_arg(arg())?),
+            "pen-opacity" => Event::PenOpacity(parse_arg(arg())?),
+            "antialias" => Event::Antialias(parse_name(&SWITCHES, arg())?),
+            "infinite-canvas" => {
+                Event::InfiniteCanvas(parse_name(&SWITCHES, arg())?)
+            }
+            "transform-filter" => {
+                Event::TransformFilter(parse_name(&FILTERS, arg())?)
+            }
+            "pressure" => Event::Pressure(PressureCurve {
+                gamma: parse_arg(arg())?,
+                min_size: parse_arg(arg())?,
+                opacity: parse_name(&SWITCHES, arg())?,
+            }),
+            "eyedropper" => Event::Eyedropper(
+                parse_arg(arg())?,
+                parse_name(&SWITCHES, arg())?,
+            ),
+            "fill" => Event::Fill(FillSetting {
+                tolerance: parse_arg(arg())?,
+                contiguous: parse_name(&SWITCHES, arg())?,
+                sample_all_layers: parse_name(&SWITCHES, arg())?,
+                expand: parse_arg(arg())?,
+                close_gaps: parse_arg(arg())?,
+            }),
+            "stabilizer-mode" => {
+                Event::StabilizerMode(parse_name(&STABILIZER_MODES, arg())?)
+            }
+            "stabilizer-strength" => {
+                Event::StabilizerStrength(parse_arg(arg())?)
+            }
+            "colors" => Event::PenColors(
+                parse_color(arg().ok_or("missing argument")?)?,
+                parse_color(arg().ok_or("missing argument")?)?,
+            ),
+            "swap-colors" => Event::SwapPenColors,
+            "active-layer" => Event::ActiveLayer(parse_arg(arg())?),
+            "add-layer" => Event::AddLayer,
+            "duplicate-layer" => Event::DuplicateLayer,
+            "delete-layer" => Event::DeleteLayer,
+            "move-layer" => Event::MoveLayer(parse_name(&DIRECTIONS, arg())?),
+            "layer-visible" => Event::LayerVisible(
+                parse_arg(arg())?,
+                parse_name(&SWITCHES, arg())?,
+            ),
+            "layer-opacity" => {
+                Event::LayerOpacity(parse_arg(arg())?, parse_arg(arg())?)
+            }
+            "new-image" => Event::NewImage(
+                (parse_arg(arg())?, parse_arg(arg())?),
+                parse_color(arg().ok_or("missing argument")?)?,
+            ),
+            "resize-canvas" => Event::ResizeCanvas(
+                (parse_arg(arg())?, parse_arg(arg())?),
+                (parse_arg(arg())?, parse_arg(arg())?),
+            ),
+            "scale-image" => Event::ScaleImage(
+                (parse_arg(arg())?, parse_arg(arg())?),
+                parse_name(&RESAMPLES, arg())?,
+            ),
+            _ => return Err(format!("unknown event `{}`", name)),
+        };
+        match words.next() {
+            Some(w) => Err(format!("unexpected `{}`", w)),
+            None => Ok(event),
+        }
+    }
+}
+
+/// Writes events with the time since the recorder was made.
+pub struct Recorder {
+    start: Instant,
+    out: Box<dyn Write>,
+}
+
+impl Recorder {
+    pub fn new(out: Box<dyn Write>) -> Recorder {
+        Recorder {
+            start: Instant::now(),
+            out,
+        }
+    }
+
+    /// Records to a new file, writing each event as soon as it comes so
+    /// that nothing is lost if the application crashes.
+    pub fn create(path: &Path) -> io::Result<Recorder> {
+        let file = File::create(path)?;
+        Ok(Recorder::new(Box::new(LineWriter::new(file))))
+    }
+
+    pub fn record(&mut self, event: &Event) -> io::Result<()> {
+        let time = self.start.elapsed().as_secs_f64();
+        writeln!(self.out, "{:.3} {}", time, event)
+    }
+}
+
+/// Parses a recorded session into the events and their times in seconds.
+pub fn parse(text: &str) -> Result<Vec<(f64, Event)>, ParseError> {
+    text.lines()
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty())
+        .map(|(i, line)| {
+            let error = |message| ParseError {
+                line: i + 1,
+                message,
+            };
+            let line = line.trim();
+            let (time, event) = line
+                .split_once(' ')
+                .ok_or_else(|| error("no event".into()))?;
+            let time = time
+                .parse::<f64>()
+                .map_err(|_| error(format!("invalid time `{}`", time)))?;
+            Ok((time, event.parse().map_err(error)?))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{cell::RefCell, rc::Rc};
+
+    /// A writer whose output can be read while the recorder owns it.
+    #[derive(Clone, Default)]
+    struct Shared(Rc<RefCell<Vec<u8>>>);
+
+    impl Write for Shared {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.borrow_mut().write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_record_and_parse() {
+        let events = [
+            Event::Resize(800, 600),
+            Event::CenterCanvas,
+            Event::Zoom((400.5, 300.0), 1.2),
+            Event::Scroll(-12.0, 0.5),
+            Event::EraserTip(true),
+            Event::Pen(PenInput {
+                x: 10.5,
+                y: -3.0,
+                pressure: 0.7,
+            }),
+            Event::StrokeEnd,
+            Event::KeyPress(65507),
+            Event::KeyRelease(65507),
+            Event::ReleaseAllKeys,
+            Event::PenSize(8.5),
+            Event::PenOpacity(0.25),
+            Event::Antialias(false),
+            Event::InfiniteCanvas(true),
+            Event::TransformFilter(Filter::Nearest),
+            Event::Pressure(PressureCurve {
+                gamma: 2.0,
+                min_size: 0.1,
+                opacity: true,
+            }),
+            Event::Eyedropper(5, true),
+            Event::Fill(FillSetting {
+                tolerance: 0.3,
+                ..FillSetting::default()
+            }),
+            Event::StabilizerMode(StabilizerMode::Rope),
+            Event::StabilizerStrength(0.4),
+            Event::PenColors(
+                RGB::new(0x1e, 0x50, 0xc8),
+                RGB::with_alpha(0xff, 0, 0, 0x80),
+            ),
+            Event::SwapPenColors,
+            Event::AddLayer,
+            Event::DuplicateLayer,
+            Event::ActiveLayer(1),
+            Event::MoveLayer(false),
+            Event::LayerVisible(0, false),
+            Event::LayerOpacity(1, 0.5),
+            Event::DeleteLayer,
+            Event::NewImage((640, 480), RGB::new(0xff, 0xff, 0xff)),
+            Event::ResizeCanvas((320, 200), (0.5, 1.0)),
+            Event::ScaleImage((100, 50), Resample::Area),
+            Event::Open(PathBuf::from("/tmp/a picture.png")),
+        ];
+        let out = Shared::default();
+        let mut recorder = Recorder::new(Box::new(out.clone()));
+        for e in &events {
+            recorder.record(e).unwrap();
+        }
+        let text = String::from_utf8(out.0.borrow().clone()).unwrap();
+        let parsed = parse(&text).unwrap();
+        assert_eq!(
+            parsed.iter().map(|(_, e)| e.clone()).collect::<Vec<_>>(),
+            events
+        );
+        assert!(parsed.windows(2).all(|w| w[0].0 <= w[1].0));
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        assert_eq!(parse("0.5 end\n\nx end").unwrap_err().line, 3);
+        assert!(parse("0.5 pen 1 2").is_err());
+        assert!(parse("0.5 end now").is_err());
+        assert!(parse("0.5 jump").is_err());
+        assert!(parse("0.5 antialias yes").is_err());
+        assert!(parse("0.5 scale-image 10 10 sharp").is_err());
+    }
+}
@@ -0,0 +1,298 @@
+//! A text format for pen strokes and the brush settings they are drawn
+//! with, so that they can be stored and painted again without a window.
+//!
+//! Each line holds one command. Settings apply to the strokes after them
+//! and `#` starts a comment:
+//!
+//! ```text
+//! canvas 320 240 ffffff
+//! color 1e50c8
+//! size 12
+//! point 10 10 0.5
+//! point 40 25 1
+//! end
+//! ```
+
+use std::{error, fmt, str::FromStr};
+
+use crate::canvas::{Canvas, PenInput, PressureCurve, RGB};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Command {
+    /// Starts over with a canvas of the size filled with the color.
+    Canvas {
+        size: (usize, usize),
+        background: RGB,
+    },
+    Color(RGB),
+    Size(f64),
+    Opacity(f64),
+    Antialias(bool),
+    Eraser(bool),
+    /// Written as the gamma, the minimum size and whether the opacity
+    /// follows the pressure.
+    Pressure(PressureCurve),
+    /// Moves the pen while it touches the canvas.
+    Point(PenInput),
+    /// Lifts the pen.
+    End,
+}
+
+pub(crate) fn format_color(c: RGB) -> String {
+    match c.unpremultiplied() {
+        (r, g, b, 0xff) => format!("{:02x}{:02x}{:02x}", r, g, b),
+        (r, g, b, a) => format!("{:02x}{:02x}{:02x}{:02x}", r, g, b, a),
+    }
+}
+
+/// Parses `rrggbb` or `rrggbbaa`.
+pub(crate) fn parse_color(s: &str) -> Result<RGB, String> {
+    let invalid = || format!("invalid color `{}`", s);
+    if !(s.len() == 6 || s.len() == 8) {
+        return Err(invalid());
+    }
+    let component = |i: usize| {
+        s.get(i..i + 2)
+            .and_then(|c| u8::from_str_radix(c, 16).ok())
+            .ok_or_else(invalid)
+    };
+    let a = if s.len() == 8 { component(6)? } else { 0xff };
+    Ok(RGB::with_alpha(
+        component(0)?,
+        component(2)?,
+        component(4)?,
+        a,
+    ))
+}
+
+fn parse_bool(s: &str) -> Result<bool, String> {
+    match s {
+        "on" => Ok(true),
+        "off" => Ok(false),
+        _ => Err(format!("expected `on` or `off`, found `{}`", s)),
+    }
+}
+
+fn on_off(b: bool) -> &'static str {
+    if b {
+        "on"
+    } else {
+        "off"
+    }
+}
+
+impl fmt::Display for Command {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Command::Canvas { size, background } => write!(
+                f,
+                "canvas {} {} {}",
+                size.0,
+                size.1,
+                format_color(*background)
+            ),
+            Command::Color(c) => write!(f, "color {}", format_color(*c)),
+            Command::Size(s) => write!(f, "size {}", s),
+            Command::Opacity(o) => write!(f, "opacity {}", o),
+            Command::Antialias(a) => write!(f, "antialias {}", on_off(*a)),
+            Command::Eraser(e) => write!(f, "eraser {}", on_off(*e)),
+            Command::Pressure(p) => write!(
+                f,
+                "pressure {} {} {}",
+                p.gamma,
+                p.min_size,
+                on_off(p.opacity)
+            ),
+            Command::Point(p) => {
+                write!(f, "point {} {} {}", p.x, p.y, p.pressure)
+            }
+            Command::End => write!(f, "end"),
+        }
+    }
+}
+
+impl FromStr for Command {
+    type Err = String;
+
+    fn from_str(line: &str) -> Result<Command, String> {
+        let mut words = line.split_whitespace();
+        let name = words.next().ok_or("empty command")?;
+        let args: Vec<&str> = words.collect();
+        let expect = |n: usize| {
+            if args.len() == n {
+                Ok(())
+            } else {
+                Err(format!("`{}` takes {} arguments", name, n))
+            }
+        };
+        let number = |s: &str| {
+            s.parse::<f64>()
+                .ok()
+                .filter(|n| n.is_finite())
+                .ok_or_else(|| format!("invalid number `{}`", s))
+        };
+        let size = |s: &str| {
+            s.parse::<usize>()
+                .ok()
+                .filter(|&n| n > 0)
+                .ok_or_else(|| format!("invalid size `{}`", s))
+        };
+        let command = match name {
+            "canvas" => {
+                expect(3)?;
+                Command::Canvas {
+                    size: (size(args[0])?, size(args[1])?),
+                    background: parse_color(args[2])?,
+                }
+            }
+            "color" => {
+                expect(1)?;
+                Command::Color(parse_color(args[0])?)
+            }
+            "size" => {
+                expect(1)?;
+                Command::Size(number(args[0])?)
+            }
+            "opacity" => {
+                expect(1)?;
+                Command::Opacity(number(args[0])?)
+            }
+            "antialias" => {
+                expect(1)?;
+                Command::Antialias(parse_bool(args[0])?)
+            }
+            "eraser" => {
+                expect(1)?;
+                Command::Eraser(parse_bool(args[0])?)
+            }
+            "pressure" => {
+                expect(3)?;
+                Command::Pressure(PressureCurve {
+                    gamma: number(args[0])?,
+                    min_size: number(args[1])?,
+                    opacity: parse_bool(args[2])?,
+                })
+            }
+            "point" => {
+                expect(3)?;
+                Command::Point(PenInput {
+                    x: number(args[0])?,
+                    y: number(args[1])?,
+                    pressure: number(args[2])?,
+                })
+            }
+            "end" => {
+                expect(0)?;
+                Command::End
+            }
+            _ => return Err(format!("unknown command `{}`", name)),
+        };
+        Ok(command)
+    }
+}
+
+impl Command {
+    pub fn apply(&self, canvas: &mut Canvas) {
+        match *self {
+            Command::Canvas { size, background } => {
+                let (foreground, _) = canvas.pen_colors();
+                canvas.set_pen_colors(foreground, background);
+                canvas.new_image(size, background);
+            }
+            Command::Color(c) => {
+                let (_, background) = canvas.pen_colors();
+                canvas.set_pen_colors(c, background);
+            }
+            Command::Size(s) => canvas.set_pen_size(s),
+            Command::Opacity(o) => canvas.set_pen_opacity(o),
+            Command::Antialias(a) => canvas.set_pen_antialias(a),
+            Command::Eraser(e) => canvas.set_eraser(e),
+            Command::Pressure(p) => canvas.set_pressure_curve(p),
+            Command::Point(p) => canvas.pen_stroke(p),
+            Command::End => canvas.pen_stroke_end(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ParseError {
+    /// Line number from 1.
+    pub line: usize,
+    pub message: String,
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.message)
+    }
+}
+
+impl error::Error for ParseError {}
+
+/// Parses the commands of a stroke file, skipping blank lines and comments.
+pub fn parse(text: &str) -> Result<Vec<Command>, ParseError> {
+    text.lines()
+        .enumerate()
+        .map(|(i, line)| (i, line.split('#').next().unwrap().trim()))
+        .filter(|(_, line)| !line.is_empty())
+        .map(|(i, line)| {
+            line.parse().map_err(|message| ParseError {
+                line: i + 1,
+                message,
+            })
+        })
+        .collect()
+}
+
+/// Paints `commands` on `canvas`, lifting the pen at the end.
+pub fn draw(commands: &[Command], canvas: &mut Canvas) {
+    for c in commands {
+        c.apply(canvas);
+    }
+    canvas.pen_stroke_end();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TEXT: &str = "
+        # A red stroke
+        canvas 40 30 ffffff
+        color ff0000
+        size 4
+        antialias off
+        pressure 2 0.5 on
+        point 10 10 1
+        point 30 10 1 # the end
+        end
+        color 0000ff80
+    ";
+
+    #[test]
+    fn test_parse() {
+        let commands = parse(TEXT).unwrap();
+        assert_eq!(commands.len(), 9);
+        assert_eq!(commands[1], Command::Color(RGB::new(0xff, 0, 0)));
+        assert_eq!(
+            commands[8],
+            Command::Color(RGB::with_alpha(0, 0, 0xff, 0x80))
+        );
+        for c in &commands {
+            assert_eq!(&c.to_string().parse::<Command>().unwrap(), c);
+        }
+        let error = parse("size 4\nsize\n").unwrap_err();
+        assert_eq!(error.line, 2);
+        assert!(parse("point 1 2 x").is_err());
+        assert!(parse("stroke").is_err());
+    }
+
+    #[test]
+    fn test_draw() {
+        let mut canvas = Canvas::new(Box::new(|_, _, _| ()), (10, 10));
+        draw(&parse(TEXT).unwrap(), &mut canvas);
+        assert_eq!(canvas.get_size(), (40, 30));
+        assert_eq!(canvas.image.get(20, 10), RGB::new(0xff, 0, 0));
+        assert_eq!(canvas.image.get(20, 20), RGB::new(0xff, 0xff, 0xff));
+    }
+}
@@ -0,0 +1,135 @@
+//! Compares brush strokes painted by `Canvas::pen_stroke` with reference
+//! images. Each `golden/NAME.txt` stroke file is painted and compared with
+//! `golden/NAME.png`. Run with `SIMPLE_PAINT_UPDATE_GOLDEN=1` to write the
+//! references after an intended change of the rasterization; the test then
+//! lists them and fails, so that the variable can't go unnoticed.
+
+use std::{fs, path::Path};
+
+use simple_paint::canvas::{Canvas, SingleVecImage, RGB};
+use simple_paint::{png, strokes};
+
+/// Largest difference allowed in each channel of a pixel.
+const TOLERANCE: u8 = 2;
+
+fn render(path: &Path) -> SingleVecImage {
+    let text = fs::read_to_string(path).unwrap();
+    let commands = strokes::parse(&text)
+        .unwrap_or_else(|e| panic!("{}: {}", path.display(), e));
+    let mut canvas = Canvas::new(Box::new(|_, _, _| ()), (32, 32));
+    strokes::draw(&commands, &mut canvas);
+    canvas.image.to_image()
+}
+
+/// Returns the number of pixels differing by more than `TOLERANCE` and an
+/// image showing them in red over a faded `expected`.
+fn compare(
+    actual: &SingleVecImage,
+    expected: &SingleVecImage,
+) -> (usize, SingleVecImage) {
+    assert_eq!(
+        (actual.width, actual.height),
+        (expected.width, expected.height),
+        "sizes differ"
+    );
+    let mut diff = SingleVecImage::new(
+        std::iter::repeat(RGB::TRANSPARENT),
+        actual.width,
+        actual.height,
+    );
+    let mut count = 0;
+    for y in 0..actual.height {
+        for x in 0..actual.width {
+            let (a, e) = (actual.get(x, y), expected.get(x, y));
+            let (a, e) = (a.unpremultiplied(), e.unpremultiplied());
+            let d = [
+                a.0.abs_diff(e.0),
+                a.1.abs_diff(e.1),
+                a.2.abs_diff(e.2),
+                a.3.abs_diff(e.3),
+            ];
+            let d = *d.iter().max().unwrap();
+            let faded = RGB::with_alpha(e.0, e.1, e.2, e.3 / 4);
+            let pixel = if d > TOLERANCE {
+                count += 1;
+                faded.over(RGB::new(0xff, 0, 0), 0.5 + d as f64 / 510.0)
+            } else {
+                faded
+            };
+            diff.set(x, y, pixel);
+        }
+    }
+    (count, diff)
+}
+
+fn save(image: &SingleVecImage, path: &Path) {
+    png::encode(image, fs::File::create(path).unwrap()).unwrap();
+}
+
+#[test]
+fn test_golden_strokes() {
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/golden");
+    let out = Path::new(env!("CARGO_TARGET_TMPDIR")).join("golden");
+    fs::create_dir_all(&out).unwrap();
+    let update = std::env::var_os("SIMPLE_PAINT_UPDATE_GOLDEN").is_some();
+    let mut paths: Vec<_> = fs::read_dir(&dir)
+        .unwrap()
+        .map(|e| e.unwrap().path())
+        .filter(|p| p.extension() == Some("txt".as_ref()))
+        .collect();
+    paths.sort();
+    assert!(!paths.is_empty());
+    let mut failures = Vec::new();
+    let mut updated = Vec::new();
+    for path in paths {
+        let name = path.file_stem().unwrap().to_string_lossy().into_owned();
+        let actual = render(&path);
+        let reference = path.with_extension("png");
+        if update {
+            save(&actual, &reference);
+            updated.push(reference.display().to_string());
+            continue;
+        }
+        let expected = match fs::File::open(&reference) {
+            Ok(file) => png::decode(file).unwrap(),
+            Err(_) => {
+                failures.push(format!("{}: no reference image", name));
+                continue;
+            }
+        };
+        let (count, diff) = compare(&actual, &expected);
+        if count > 0 {
+            let diff_path = out.join(format!("{}.diff.png", name));
+            save(&diff, &diff_path);
+            save(&actual, &out.join(format!("{}.png", name)));
+            failures.push(format!(
+                "{}: {} pixels differ, see {}",
+                name,
+                count,
+                diff_path.display()
+            ));
+        }
+    }
+    assert!(
+        updated.is_empty(),
+        "wrote the references below; rerun without \
+         SIMPLE_PAINT_UPDATE_GOLDEN to check them\n{}",
+        updated.join("\n")
+    );
+    assert!(failures.is_empty(), "\n{}", failures.join("\n"));
+}
+
+#[test]
+fn test_compare() {
+    let image =
+        SingleVecImage::new(std::iter::repeat(RGB::new(10, 10, 10)), 3, 2);
+    let mut close = image.clone();
+    close.set(0, 0, RGB::new(12, 10, 8));
+    assert_eq!(compare(&close, &image).0, 0);
+    let mut far = image.clone();
+    far.set(2, 1, RGB::new(10, 20, 10));
+    far.set(1, 1, RGB::with_alpha(10, 10, 10, 0x80));
+    let (count, diff) = compare(&far, &image);
+    assert_eq!(count, 2);
+    assert!(diff.get(2, 1).r() > diff.get(0, 0).r());
+}
@@ -1,29 +1,79 @@
 use gtk::prelude::*;
-use std::cell::{RefCell, RefMut};
+use std::cell::{Cell, RefCell, RefMut};
 use std::rc::Rc;
 
+mod color;
+mod file;
+mod fill;
+mod image;
+mod layers;
+mod pressure;
+mod session;
 mod viewport;
 use viewport::PenInput;
 
+/// Pressure used for devices without a pressure axis, such as mice.
+const DEFAULT_PRESSURE: f64 = 0.7;
+
 fn event_cb(
     position: (f64, f64),
     pressure: Option<f64>,
     event_type: gdk::EventType,
+    source_device: Option<gdk::Device>,
     mut viewport: RefMut<viewport::Viewport>,
 ) -> gtk::Inhibit {
     let (x, y) = position;
+    viewport.set_eraser_tip(
+        source_device.map(|d| d.get_source()) == Some(gdk::InputSource::Eraser),
+    );
     use gdk::EventType::*;
     match event_type {
         ButtonPress | MotionNotify => viewport.pen_stroke(PenInput {
             x,
             y,
-            pressure: pressure.unwrap_or(0.7),
+            pressure: pressure.unwrap_or(DEFAULT_PRESSURE),
         }),
         _ => (),
     };
     gtk::Inhibit(false)
 }
 
+/// Reads the history memory budget in MiB from `SIMPLE_PAINT_HISTORY_MB`.
+fn history_memory_budget() -> usize {
+    std::env::var("SIMPLE_PAINT_HISTORY_MB")
+        .ok()
+        .and_then(|v| v.parse::<usize>().ok())
+        .map_or(viewport::DEFAULT_MEMORY_BUDGET, |mb| mb * 1024 * 1024)
+}
+
+/// Distance in pixels the canvas moves per wheel step.
+const SCROLL_STEP: f64 = 48.0;
+
+/// Milliseconds between steps of the marching ants around the selection.
+const ANTS_INTERVAL: u32 = 200;
+
+fn scroll_cb(
+    e: &gdk::EventScroll,
+    mut viewport: RefMut<viewport::Viewport>,
+) -> gtk::Inhibit {
+    let (dx, dy) = match e.get_direction() {
+        gdk::ScrollDirection::Up => (0.0, -1.0),
+        gdk::ScrollDirection::Down => (0.0, 1.0),
+        gdk::ScrollDirection::Left => (-1.0, 0.0),
+        gdk::ScrollDirection::Right => (1.0, 0.0),
+        _ => e.get_delta(),
+    };
+    let state = e.get_state();
+    if state.contains(gdk::ModifierType::CONTROL_MASK) {
+        viewport.zoom_at(e.get_position(), 1.2_f64.powf(-dy));
+    } else if state.contains(gdk::ModifierType::SHIFT_MASK) {
+        viewport.scroll(-dy * SCROLL_STEP, -dx * SCROLL_STEP);
+    } else {
+        viewport.scroll(-dx * SCROLL_STEP, -dy * SCROLL_STEP);
+    }
+    gtk::Inhibit(true)
+}
+
 fn make_connect_configure_event_cb(
     surface: Rc<RefCell<Option<cairo::Surface>>>,
     context: Rc<RefCell<Option<cairo::Context>>>,
@@ -56,6 +106,10 @@ pub fn build_ui(application: &gtk::Application) {
     window.set_position(gtk::WindowPosition::Center);
     window.set_default_size(1000, 800);
 
+    let header = gtk::HeaderBar::new();
+    header.set_show_close_button(true);
+    window.set_titlebar(Some(&header));
+
     let drawing = Rc::new(gtk::DrawingArea::new());
 
     drawing.add_events(gdk::EventMask::BUTTON1_MOTION_MASK);
@@ -63,6 +117,10 @@ pub fn build_ui(application: &gtk::Application) {
     drawing.add_events(gdk::EventMask::BUTTON_RELEASE_MASK);
     drawing.add_events(gdk::EventMask::KEY_PRESS_MASK);
     drawing.add_events(gdk::EventMask::KEY_RELEASE_MASK);
+    drawing.add_events(gdk::EventMask::FOCUS_CHANGE_MASK);
+    drawing.add_events(gdk::EventMask::SCROLL_MASK);
+    drawing.add_events(gdk::EventMask::SMOOTH_SCROLL_MASK);
+    drawing.add_events(gdk::EventMask::TOUCHPAD_GESTURE_MASK);
 
     let surface: Rc<RefCell<Option<cairo::Surface>>> =
         Rc::new(RefCell::new(None));
@@ -76,6 +134,10 @@ pub fn build_ui(application: &gtk::Application) {
             Box::new(move || drawing_clone.queue_draw())
         },
     )));
+    viewport
+        .borrow_mut()
+        .set_history_memory_budget(history_memory_budget());
+    session::start_recording(&viewport);
 
     drawing.connect_configure_event(make_connect_configure_event_cb(
         Rc::clone(&surface),
@@ -89,6 +151,7 @@ pub fn build_ui(application: &gtk::Application) {
             e.get_position(),
             e.get_axis(gdk::AxisUse::Pressure),
             e.get_event_type(),
+            e.get_source_device(),
             viewport_clone.borrow_mut(),
         )
     });
@@ -99,26 +162,60 @@ pub fn build_ui(application: &gtk::Application) {
             e.get_position(),
             e.get_axis(gdk::AxisUse::Pressure),
             e.get_event_type(),
+            e.get_source_device(),
             viewport_clone.borrow_mut(),
         )
     });
 
+    let viewport_clone = viewport.clone();
+    drawing.connect_scroll_event(move |_, e| {
+        scroll_cb(e, viewport_clone.borrow_mut())
+    });
+
+    let zoom_gesture = gtk::GestureZoom::new(&*drawing);
+    let last_scale = Rc::new(Cell::new(1.0));
+    let last_scale_clone = last_scale.clone();
+    zoom_gesture.connect_begin(move |_, _| last_scale_clone.set(1.0));
+    let viewport_clone = viewport.clone();
+    zoom_gesture.connect_scale_changed(move |g, scale| {
+        if let Some(center) = g.get_bounding_box_center() {
+            viewport_clone
+                .borrow_mut()
+                .zoom_at(center, scale / last_scale.get());
+            last_scale.set(scale);
+        }
+    });
+    // Widgets do not keep their gestures alive, so the drawing area owns
+    // this one through its destroy handler.
+    drawing.connect_destroy(move |_| {
+        zoom_gesture.reset();
+    });
+
     drawing.connect_draw(move |_, c| {
         c.set_source_surface(surface.borrow().as_ref().unwrap(), 0.0, 0.0);
         c.paint();
         gtk::Inhibit(false)
     });
 
+    drawing.set_can_focus(true);
+    let layer_panel = layers::LayerPanel::new(viewport.clone());
+    let color_panel = color::ColorPanel::new(viewport.clone());
+
     let viewport_clone = viewport.clone();
+    let color_panel_clone = color_panel.clone();
     drawing.connect_button_release_event(move |_, _| {
         viewport_clone.borrow_mut().pen_stroke_end();
+        color_panel_clone.refresh();
         gtk::Inhibit(false)
     });
 
-    drawing.set_can_focus(true);
     let viewport_clone = viewport.clone();
+    let layer_panel_clone = layer_panel.clone();
+    let color_panel_clone = color_panel.clone();
     drawing.connect_key_press_event(move |_, key| {
         viewport_clone.borrow_mut().key_press(key.get_keyval());
+        layer_panel_clone.refresh();
+        color_panel_clone.refresh();
         gtk::Inhibit(false)
     });
 
@@ -128,11 +225,34 @@ pub fn build_ui(application: &gtk::Application) {
         gtk::Inhibit(false)
     });
 
+    let viewport_clone = viewport.clone();
+    drawing.connect_focus_out_event(move |_, _| {
+        viewport_clone.borrow_mut().release_all_keys();
+        gtk::Inhibit(false)
+    });
+
     let viewport_clone = viewport.clone();
     drawing.connect_realize(move |_| {
         viewport_clone.borrow_mut().set_canvas_center();
     });
 
+    let layer_panel_clone = layer_panel.clone();
+    let color_panel_clone = color_panel.clone();
+    session::start_replay(
+        viewport.clone(),
+        context,
+        Box::new(move || {
+            layer_panel_clone.refresh();
+            color_panel_clone.refresh();
+        }),
+    );
+
+    let viewport_clone = viewport.clone();
+    glib::timeout_add_local(ANTS_INTERVAL, move || {
+        viewport_clone.borrow_mut().march_ants();
+        glib::Continue(true)
+    });
+
     drawing.set_hexpand(true);
     drawing.set_vexpand(true);
 
@@ -143,13 +263,133 @@ pub fn build_ui(application: &gtk::Application) {
     scale.set_vexpand(true);
     scale.set_can_focus(false);
 
+    let viewport_clone = viewport.clone();
     scale.connect_value_changed(move |s| {
-        viewport.borrow_mut().set_pen_size(s.get_value())
+        viewport_clone.borrow_mut().set_pen_size(s.get_value())
     });
 
+    let opacity = gtk::Scale::new(
+        gtk::Orientation::Vertical,
+        Some(&gtk::Adjustment::new(100.0, 0.0, 100.0, 1.0, 10.0, 0.0)),
+    );
+    opacity.set_vexpand(true);
+    opacity.set_can_focus(false);
+    opacity.set_tooltip_text(Some("Pen opacity"));
+
+    let viewport_clone = viewport.clone();
+    opacity.connect_value_changed(move |s| {
+        viewport_clone
+            .borrow_mut()
+            .set_pen_opacity(s.get_value() / 100.0)
+    });
+
+    let antialias = gtk::ToggleButton::with_label("AA");
+    antialias.set_active(true);
+    antialias.set_can_focus(false);
+    antialias.set_tooltip_text(Some("Anti-aliasing"));
+
+    let viewport_clone = viewport.clone();
+    antialias.connect_toggled(move |b| {
+        viewport_clone
+            .borrow_mut()
+            .set_pen_antialias(b.get_active())
+    });
+
+    let infinite = gtk::ToggleButton::with_label("∞");
+    infinite.set_can_focus(false);
+    infinite.set_tooltip_text(Some("Grow the canvas when drawing beyond it"));
+
+    let viewport_clone = viewport.clone();
+    infinite.connect_toggled(move |b| {
+        viewport_clone
+            .borrow_mut()
+            .set_infinite_canvas(b.get_active())
+    });
+
+    let stabilizer = gtk::Scale::new(
+        gtk::Orientation::Vertical,
+        Some(&gtk::Adjustment::new(0.0, 0.0, 100.0, 1.0, 10.0, 0.0)),
+    );
+    stabilizer.set_vexpand(true);
+    stabilizer.set_can_focus(false);
+    stabilizer.set_tooltip_text(Some("Stabilizer strength"));
+
+    let viewport_clone = viewport.clone();
+    stabilizer.connect_value_changed(move |s| {
+        viewport_clone
+            .borrow_mut()
+            .set_stabilizer_strength(s.get_value() / 100.0)
+    });
+
+    let stabilizer_mode = gtk::ComboBoxText::new();
+    stabilizer_mode.append(Some("average"), "Average");
+    stabilizer_mode.append(Some("rope"), "Rope");
+    stabilizer_mode.set_active_id(Some("average"));
+    stabilizer_mode.set_can_focus(false);
+    stabilizer_mode.set_tooltip_text(Some("Stabilizer"));
+
+    let viewport_clone = viewport.clone();
+    stabilizer_mode.connect_changed(move |c| {
+        let mode = match c.get_active_id().as_deref() {
+            Some("rope") => viewport::StabilizerMode::Rope,
+            _ => viewport::StabilizerMode::Average,
+        };
+        viewport_clone.borrow_mut().set_stabilizer_mode(mode)
+    });
+
+    let transform_filter = gtk::ComboBoxText::new();
+    transform_filter.append(Some("bilinear"), "Smooth");
+    transform_filter.append(Some("nearest"), "Pixelated");
+    transform_filter.set_active_id(Some("bilinear"));
+    transform_filter.set_can_focus(false);
+    transform_filter.set_tooltip_text(Some("Transform resampling"));
+
+    let viewport_clone = viewport.clone();
+    transform_filter.connect_changed(move |c| {
+        let filter = match c.get_active_id().as_deref() {
+            Some("nearest") => viewport::Filter::Nearest,
+            _ => viewport::Filter::Bilinear,
+        };
+        viewport_clone.borrow_mut().set_transform_filter(filter)
+    });
+
+    pressure::add_pressure_button(&window, &header, viewport.clone());
+    fill::add_fill_options(&header, viewport.clone());
+
+    let layer_panel_clone = layer_panel.clone();
+    file::add_file_actions(
+        application,
+        &window,
+        &header,
+        viewport.clone(),
+        Box::new(move || layer_panel_clone.refresh()),
+    );
+
+    let layer_panel_clone = layer_panel.clone();
+    image::add_image_actions(
+        application,
+        &window,
+        &header,
+        viewport,
+        Box::new(move || layer_panel_clone.refresh()),
+    );
+
+    let pen_scales = gtk::Box::new(gtk::Orientation::Horizontal, 0);
+    pen_scales.add(&scale);
+    pen_scales.add(&opacity);
+    pen_scales.add(&stabilizer);
+    let tools = gtk::Box::new(gtk::Orientation::Vertical, 0);
+    tools.add(&color_panel.widget);
+    tools.add(&antialias);
+    tools.add(&infinite);
+    tools.add(&stabilizer_mode);
+    tools.add(&transform_filter);
+    tools.add(&pen_scales);
+
     let grid = gtk::Grid::new();
     grid.add(&*drawing);
-    grid.add(&scale);
+    grid.add(&tools);
+    grid.add(&layer_panel.widget);
 
     window.add(&grid);
     window.show_all();
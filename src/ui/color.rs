@@ -0,0 +1,121 @@
+use gtk::prelude::*;
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use super::viewport::{Viewport, RGB};
+
+fn to_gdk(c: RGB) -> gdk::RGBA {
+    let (r, g, b, a) = c.unpremultiplied();
+    gdk::RGBA {
+        red: r as f64 / 0xff as f64,
+        green: g as f64 / 0xff as f64,
+        blue: b as f64 / 0xff as f64,
+        alpha: a as f64 / 0xff as f64,
+    }
+}
+
+fn from_gdk(c: gdk::RGBA) -> RGB {
+    let f = |v: f64| (v * 0xff as f64).round() as u8;
+    RGB::new(f(c.red), f(c.green), f(c.blue))
+}
+
+/// Foreground and background color buttons with a swap button and the
+/// eyedropper options.
+pub struct ColorPanel {
+    pub widget: gtk::Box,
+    foreground: gtk::ColorButton,
+    background: gtk::ColorButton,
+    viewport: Rc<RefCell<Viewport>>,
+}
+
+impl ColorPanel {
+    pub fn new(viewport: Rc<RefCell<Viewport>>) -> Rc<ColorPanel> {
+        let widget = gtk::Box::new(gtk::Orientation::Vertical, 0);
+        let foreground = gtk::ColorButton::new();
+        foreground.set_tooltip_text(Some("Foreground color"));
+        let background = gtk::ColorButton::new();
+        background.set_tooltip_text(Some("Background color"));
+        let swap = gtk::Button::from_icon_name(
+            Some("object-flip-vertical-symbolic"),
+            gtk::IconSize::Button,
+        );
+        swap.set_tooltip_text(Some("Swap colors (X)"));
+        for b in &[
+            foreground.clone().upcast::<gtk::Button>(),
+            background.clone().upcast(),
+            swap.clone(),
+        ] {
+            b.set_can_focus(false);
+            widget.add(b);
+        }
+
+        let panel = Rc::new(ColorPanel {
+            widget,
+            foreground,
+            background,
+            viewport,
+        });
+
+        for b in &[&panel.foreground, &panel.background] {
+            let panel_clone = panel.clone();
+            b.connect_color_set(move |_| {
+                panel_clone.viewport.borrow_mut().set_pen_colors(
+                    from_gdk(panel_clone.foreground.get_rgba()),
+                    from_gdk(panel_clone.background.get_rgba()),
+                );
+            });
+        }
+
+        let panel_clone = panel.clone();
+        swap.connect_clicked(move |_| {
+            panel_clone.viewport.borrow_mut().swap_pen_colors();
+            panel_clone.refresh();
+        });
+
+        let (size, merged) = panel.viewport.borrow().eyedropper_setting();
+        let sample_size = gtk::ComboBoxText::new();
+        for &s in &[1, 3, 5] {
+            sample_size.append(Some(&s.to_string()), &format!("{0}x{0}", s));
+        }
+        sample_size.set_active_id(Some(&size.to_string()));
+        sample_size.set_tooltip_text(Some("Eyedropper sample size (Alt)"));
+        let sample_merged = gtk::CheckButton::with_label("Merged");
+        sample_merged.set_active(merged);
+        sample_merged.set_tooltip_text(Some("Sample all layers"));
+        for w in &[
+            sample_size.clone().upcast::<gtk::Widget>(),
+            sample_merged.clone().upcast(),
+        ] {
+            w.set_can_focus(false);
+            panel.widget.add(w);
+        }
+        let update = {
+            let panel = panel.clone();
+            let sample_size = sample_size.clone();
+            let sample_merged = sample_merged.clone();
+            Rc::new(move || {
+                let size = sample_size
+                    .get_active_id()
+                    .and_then(|id| id.parse().ok())
+                    .unwrap_or(1);
+                panel
+                    .viewport
+                    .borrow_mut()
+                    .set_eyedropper_setting(size, sample_merged.get_active());
+            })
+        };
+        let update_clone = update.clone();
+        sample_size.connect_changed(move |_| update_clone());
+        sample_merged.connect_toggled(move |_| update());
+
+        panel.refresh();
+        panel
+    }
+
+    /// Shows the colors currently set in the viewport.
+    pub fn refresh(&self) {
+        let (foreground, background) = self.viewport.borrow().pen_colors();
+        self.foreground.set_rgba(&to_gdk(foreground));
+        self.background.set_rgba(&to_gdk(background));
+    }
+}
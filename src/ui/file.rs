@@ -0,0 +1,188 @@
+use gio::prelude::*;
+use gtk::prelude::*;
+use std::cell::RefCell;
+use std::path::PathBuf;
+use std::rc::Rc;
+
+use super::image;
+use super::viewport::Viewport;
+
+fn png_filter() -> gtk::FileFilter {
+    let filter = gtk::FileFilter::new();
+    filter.set_name(Some("PNG image"));
+    filter.add_mime_type("image/png");
+    filter.add_pattern("*.png");
+    filter
+}
+
+fn choose_file(
+    window: &gtk::ApplicationWindow,
+    action: gtk::FileChooserAction,
+) -> Option<PathBuf> {
+    let (title, accept) = match action {
+        gtk::FileChooserAction::Save => ("Save Image", "_Save"),
+        _ => ("Open Image", "_Open"),
+    };
+    let dialog = gtk::FileChooserDialog::with_buttons(
+        Some(title),
+        Some(window),
+        action,
+        &[
+            ("_Cancel", gtk::ResponseType::Cancel),
+            (accept, gtk::ResponseType::Accept),
+        ],
+    );
+    dialog.add_filter(&png_filter());
+    if action == gtk::FileChooserAction::Save {
+        dialog.set_do_overwrite_confirmation(true);
+        dialog.set_current_name("untitled.png");
+    }
+    let path = if dialog.run() == gtk::ResponseType::Accept {
+        dialog.get_filename()
+    } else {
+        None
+    };
+    dialog.close();
+    path
+}
+
+fn show_error(window: &gtk::ApplicationWindow, message: &str) {
+    let dialog = gtk::MessageDialog::new(
+        Some(window),
+        gtk::DialogFlags::MODAL,
+        gtk::MessageType::Error,
+        gtk::ButtonsType::Close,
+        message,
+    );
+    dialog.run();
+    dialog.close();
+}
+
+fn set_title(header: &gtk::HeaderBar, path: &Option<PathBuf>) {
+    let title = match path.as_ref().and_then(|p| p.file_name()) {
+        Some(name) => format!("{} - Simple Paint", name.to_string_lossy()),
+        None => "Simple Paint".to_string(),
+    };
+    header.set_title(Some(&title));
+}
+
+struct Document {
+    window: gtk::ApplicationWindow,
+    header: gtk::HeaderBar,
+    viewport: Rc<RefCell<Viewport>>,
+    path: RefCell<Option<PathBuf>>,
+    on_open: Box<dyn Fn()>,
+}
+
+impl Document {
+    fn new_image(&self) {
+        if image::new_image(&self.window, &self.viewport) {
+            *self.path.borrow_mut() = None;
+            set_title(&self.header, &self.path.borrow());
+            (self.on_open)();
+        }
+    }
+
+    fn open(&self) {
+        let path = match choose_file(&self.window, gtk::FileChooserAction::Open)
+        {
+            Some(p) => p,
+            None => return,
+        };
+        let result = self.viewport.borrow_mut().open_png(&path);
+        match result {
+            Ok(()) => {
+                *self.path.borrow_mut() = Some(path);
+                set_title(&self.header, &self.path.borrow());
+                (self.on_open)();
+            }
+            Err(e) => show_error(
+                &self.window,
+                &format!("Could not open {}: {}", path.display(), e),
+            ),
+        }
+    }
+
+    fn save(&self) {
+        let path = self.path.borrow().clone();
+        match path {
+            Some(path) => self.save_to(path),
+            None => self.save_as(),
+        }
+    }
+
+    fn save_as(&self) {
+        if let Some(path) =
+            choose_file(&self.window, gtk::FileChooserAction::Save)
+        {
+            self.save_to(path);
+        }
+    }
+
+    fn save_to(&self, path: PathBuf) {
+        let result = self.viewport.borrow().save_png(&path);
+        match result {
+            Ok(()) => {
+                *self.path.borrow_mut() = Some(path);
+                set_title(&self.header, &self.path.borrow());
+            }
+            Err(e) => show_error(
+                &self.window,
+                &format!("Could not save {}: {}", path.display(), e),
+            ),
+        }
+    }
+}
+
+fn add_action(
+    window: &gtk::ApplicationWindow,
+    name: &str,
+    document: &Rc<Document>,
+    f: fn(&Document),
+) {
+    let action = gio::SimpleAction::new(name, None);
+    let document = document.clone();
+    action.connect_activate(move |_, _| f(&document));
+    window.add_action(&action);
+}
+
+/// Adds New, Open, Save and Save As to the header bar, with the usual
+/// keyboard shortcuts. `on_open` is called after an image is created or
+/// opened.
+pub fn add_file_actions(
+    application: &gtk::Application,
+    window: &gtk::ApplicationWindow,
+    header: &gtk::HeaderBar,
+    viewport: Rc<RefCell<Viewport>>,
+    on_open: Box<dyn Fn()>,
+) {
+    let document = Rc::new(Document {
+        window: window.clone(),
+        header: header.clone(),
+        viewport,
+        path: RefCell::new(None),
+        on_open,
+    });
+    set_title(header, &document.path.borrow());
+
+    add_action(window, "new", &document, Document::new_image);
+    add_action(window, "open", &document, Document::open);
+    add_action(window, "save", &document, Document::save);
+    add_action(window, "save-as", &document, Document::save_as);
+    application.set_accels_for_action("win.new", &["<Primary>n"]);
+    application.set_accels_for_action("win.open", &["<Primary>o"]);
+    application.set_accels_for_action("win.save", &["<Primary>s"]);
+    application.set_accels_for_action("win.save-as", &["<Primary><Shift>s"]);
+
+    for &(label, action) in &[
+        ("New", "win.new"),
+        ("Open", "win.open"),
+        ("Save", "win.save"),
+        ("Save As", "win.save-as"),
+    ] {
+        let button = gtk::Button::with_label(label);
+        button.set_action_name(Some(action));
+        button.set_can_focus(false);
+        header.pack_start(&button);
+    }
+}
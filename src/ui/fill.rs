@@ -0,0 +1,96 @@
+use gtk::prelude::*;
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use super::viewport::{FillSetting, Viewport};
+
+/// Options of the fill tool, shown in a popover of the header bar.
+struct FillOptions {
+    tolerance: gtk::Scale,
+    contiguous: gtk::CheckButton,
+    sample_all_layers: gtk::CheckButton,
+    expand: gtk::SpinButton,
+    close_gaps: gtk::SpinButton,
+    viewport: Rc<RefCell<Viewport>>,
+}
+
+impl FillOptions {
+    fn update(&self) {
+        self.viewport.borrow_mut().set_fill_setting(FillSetting {
+            tolerance: self.tolerance.get_value() / 100.0,
+            contiguous: self.contiguous.get_active(),
+            sample_all_layers: self.sample_all_layers.get_active(),
+            expand: self.expand.get_value_as_int() as usize,
+            close_gaps: self.close_gaps.get_value_as_int() as usize,
+        });
+    }
+}
+
+pub fn add_fill_options(
+    header: &gtk::HeaderBar,
+    viewport: Rc<RefCell<Viewport>>,
+) {
+    let setting = viewport.borrow().fill_setting();
+    let tolerance =
+        gtk::Scale::with_range(gtk::Orientation::Horizontal, 0.0, 100.0, 1.0);
+    tolerance.set_value(setting.tolerance * 100.0);
+    tolerance.set_digits(0);
+    tolerance.set_size_request(160, -1);
+    let contiguous = gtk::CheckButton::with_label("Contiguous");
+    contiguous.set_active(setting.contiguous);
+    let sample_all_layers = gtk::CheckButton::with_label("Sample all layers");
+    sample_all_layers.set_active(setting.sample_all_layers);
+    let expand = gtk::SpinButton::with_range(0.0, 10.0, 1.0);
+    expand.set_value(setting.expand as f64);
+    let close_gaps = gtk::SpinButton::with_range(0.0, 20.0, 1.0);
+    close_gaps.set_value(setting.close_gaps as f64);
+
+    let grid = gtk::Grid::new();
+    grid.set_row_spacing(4);
+    grid.set_column_spacing(8);
+    grid.set_border_width(8);
+    let rows: [(&str, gtk::Widget); 3] = [
+        ("Tolerance %", tolerance.clone().upcast()),
+        ("Expand (px)", expand.clone().upcast()),
+        ("Close gaps (px)", close_gaps.clone().upcast()),
+    ];
+    for (row, (label, widget)) in rows.iter().enumerate() {
+        let label = gtk::Label::new(Some(*label));
+        label.set_xalign(0.0);
+        grid.attach(&label, 0, row as i32, 1, 1);
+        grid.attach(widget, 1, row as i32, 1, 1);
+    }
+    grid.attach(&contiguous, 0, 3, 2, 1);
+    grid.attach(&sample_all_layers, 0, 4, 2, 1);
+    grid.show_all();
+
+    let button = gtk::MenuButton::new();
+    button.set_label("Fill");
+    button.set_tooltip_text(Some("Fill tool options (F)"));
+    button.set_can_focus(false);
+    let popover = gtk::Popover::new(Some(&button));
+    popover.add(&grid);
+    button.set_popover(Some(&popover));
+    header.pack_end(&button);
+
+    let options = Rc::new(FillOptions {
+        tolerance,
+        contiguous,
+        sample_all_layers,
+        expand,
+        close_gaps,
+        viewport,
+    });
+    let options_clone = options.clone();
+    options
+        .tolerance
+        .connect_value_changed(move |_| options_clone.update());
+    for b in &[&options.contiguous, &options.sample_all_layers] {
+        let options_clone = options.clone();
+        b.connect_toggled(move |_| options_clone.update());
+    }
+    for s in &[&options.expand, &options.close_gaps] {
+        let options_clone = options.clone();
+        s.connect_value_changed(move |_| options_clone.update());
+    }
+}
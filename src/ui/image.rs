@@ -0,0 +1,245 @@
+use gio::prelude::*;
+use gtk::prelude::*;
+use std::cell::{Cell, RefCell};
+use std::rc::Rc;
+
+use super::viewport::{Resample, Viewport, RGB};
+
+/// Largest width or height accepted by the dialogs.
+const MAX_SIZE: f64 = 16384.0;
+
+type ShowDialog = fn(&gtk::ApplicationWindow, &Rc<RefCell<Viewport>>);
+
+/// Shows a modal dialog with `content` and OK and Cancel buttons. Returns
+/// whether OK was pressed.
+fn run_dialog(
+    window: &gtk::ApplicationWindow,
+    title: &str,
+    content: &gtk::Grid,
+) -> bool {
+    let dialog = gtk::Dialog::with_buttons(
+        Some(title),
+        Some(window),
+        gtk::DialogFlags::MODAL | gtk::DialogFlags::DESTROY_WITH_PARENT,
+        &[
+            ("_Cancel", gtk::ResponseType::Cancel),
+            ("_OK", gtk::ResponseType::Ok),
+        ],
+    );
+    dialog.set_default_response(gtk::ResponseType::Ok);
+    content.set_row_spacing(4);
+    content.set_column_spacing(8);
+    content.set_border_width(8);
+    dialog.get_content_area().add(content);
+    dialog.show_all();
+    let ok = dialog.run() == gtk::ResponseType::Ok;
+    dialog.close();
+    ok
+}
+
+/// Width and height fields starting at `size`, attached to the first two
+/// rows of `grid`.
+fn size_fields(
+    grid: &gtk::Grid,
+    size: (usize, usize),
+) -> (gtk::SpinButton, gtk::SpinButton) {
+    let width = gtk::SpinButton::with_range(1.0, MAX_SIZE, 1.0);
+    width.set_value(size.0 as f64);
+    width.set_activates_default(true);
+    let height = gtk::SpinButton::with_range(1.0, MAX_SIZE, 1.0);
+    height.set_value(size.1 as f64);
+    height.set_activates_default(true);
+    for (row, &(label, field)) in
+        [("Width (px)", &width), ("Height (px)", &height)]
+            .iter()
+            .enumerate()
+    {
+        let label = gtk::Label::new(Some(label));
+        label.set_xalign(0.0);
+        grid.attach(&label, 0, row as i32, 1, 1);
+        grid.attach(field, 1, row as i32, 1, 1);
+    }
+    (width, height)
+}
+
+fn get_size(
+    width: &gtk::SpinButton,
+    height: &gtk::SpinButton,
+) -> (usize, usize) {
+    (
+        width.get_value_as_int() as usize,
+        height.get_value_as_int() as usize,
+    )
+}
+
+/// Asks for the size and background of a new image and creates it. Returns
+/// whether it was created.
+pub fn new_image(
+    window: &gtk::ApplicationWindow,
+    viewport: &Rc<RefCell<Viewport>>,
+) -> bool {
+    let grid = gtk::Grid::new();
+    let (width, height) = size_fields(&grid, viewport.borrow().canvas_size());
+    let background = gtk::ComboBoxText::new();
+    background.append(Some("white"), "White");
+    background.append(Some("background"), "Background color");
+    background.append(Some("transparent"), "Transparent");
+    background.set_active_id(Some("white"));
+    let label = gtk::Label::new(Some("Background"));
+    label.set_xalign(0.0);
+    grid.attach(&label, 0, 2, 1, 1);
+    grid.attach(&background, 1, 2, 1, 1);
+    if !run_dialog(window, "New Image", &grid) {
+        return false;
+    }
+    let mut viewport = viewport.borrow_mut();
+    let color = match background.get_active_id().as_deref() {
+        Some("background") => viewport.pen_colors().1,
+        Some("transparent") => RGB::TRANSPARENT,
+        _ => RGB::new(0xff, 0xff, 0xff),
+    };
+    viewport.new_image(get_size(&width, &height), color);
+    true
+}
+
+fn resize_canvas(
+    window: &gtk::ApplicationWindow,
+    viewport: &Rc<RefCell<Viewport>>,
+) {
+    let grid = gtk::Grid::new();
+    let (width, height) = size_fields(&grid, viewport.borrow().canvas_size());
+    // Nine buttons choosing which side the old canvas sticks to.
+    let anchors = gtk::Grid::new();
+    let mut group: Option<gtk::RadioButton> = None;
+    let mut buttons = Vec::new();
+    for row in 0..3 {
+        for column in 0..3 {
+            let button = match &group {
+                Some(g) => gtk::RadioButton::from_widget(g),
+                None => gtk::RadioButton::new(),
+            };
+            button.set_mode(false);
+            button.set_size_request(28, 28);
+            anchors.attach(&button, column, row, 1, 1);
+            if group.is_none() {
+                group = Some(button.clone());
+            }
+            buttons.push(((column as f64 / 2.0, row as f64 / 2.0), button));
+        }
+    }
+    buttons[4].1.set_active(true);
+    let label = gtk::Label::new(Some("Anchor"));
+    label.set_xalign(0.0);
+    grid.attach(&label, 0, 2, 1, 1);
+    grid.attach(&anchors, 1, 2, 1, 1);
+    if !run_dialog(window, "Canvas Size", &grid) {
+        return;
+    }
+    let anchor = buttons
+        .iter()
+        .find(|(_, b)| b.get_active())
+        .map_or((0.5, 0.5), |(a, _)| *a);
+    viewport
+        .borrow_mut()
+        .resize_canvas(get_size(&width, &height), anchor);
+}
+
+fn scale_image(
+    window: &gtk::ApplicationWindow,
+    viewport: &Rc<RefCell<Viewport>>,
+) {
+    let grid = gtk::Grid::new();
+    let size = viewport.borrow().canvas_size();
+    let (width, height) = size_fields(&grid, size);
+    let keep_ratio = gtk::CheckButton::with_label("Keep aspect ratio");
+    keep_ratio.set_active(true);
+    grid.attach(&keep_ratio, 0, 2, 2, 1);
+    let method = gtk::ComboBoxText::new();
+    method.append(Some("nearest"), "Nearest neighbor");
+    method.append(Some("bilinear"), "Bilinear");
+    method.append(Some("bicubic"), "Bicubic");
+    method.append(Some("area"), "Area average");
+    method.set_active_id(Some("bicubic"));
+    let label = gtk::Label::new(Some("Resampling"));
+    label.set_xalign(0.0);
+    grid.attach(&label, 0, 3, 1, 1);
+    grid.attach(&method, 1, 3, 1, 1);
+
+    // Each field follows the other while the aspect ratio is kept. The
+    // flag stops the update from bouncing back.
+    let updating = Rc::new(Cell::new(false));
+    let ratio = size.1 as f64 / size.0 as f64;
+    for &(from, to, r) in
+        &[(&width, &height, ratio), (&height, &width, 1.0 / ratio)]
+    {
+        let to = to.clone();
+        let keep_ratio = keep_ratio.clone();
+        let updating = updating.clone();
+        from.connect_value_changed(move |from| {
+            if keep_ratio.get_active() && !updating.get() {
+                updating.set(true);
+                to.set_value((from.get_value() * r).round().max(1.0));
+                updating.set(false);
+            }
+        });
+    }
+
+    if !run_dialog(window, "Scale Image", &grid) {
+        return;
+    }
+    let method = match method.get_active_id().as_deref() {
+        Some("nearest") => Resample::Nearest,
+        Some("bilinear") => Resample::Bilinear,
+        Some("area") => Resample::Area,
+        _ => Resample::Bicubic,
+    };
+    viewport
+        .borrow_mut()
+        .scale_image(get_size(&width, &height), method);
+}
+
+/// Adds an Image menu to the header bar with Canvas Size and Scale Image.
+/// `on_change` is called after the image changed.
+pub fn add_image_actions(
+    application: &gtk::Application,
+    window: &gtk::ApplicationWindow,
+    header: &gtk::HeaderBar,
+    viewport: Rc<RefCell<Viewport>>,
+    on_change: Box<dyn Fn()>,
+) {
+    let on_change: Rc<dyn Fn()> = on_change.into();
+    let menu = gio::Menu::new();
+    for &(name, label, accel, f) in &[
+        (
+            "canvas-size",
+            "Canvas Size…",
+            "<Primary><Alt>c",
+            resize_canvas as ShowDialog,
+        ),
+        (
+            "scale-image",
+            "Scale Image…",
+            "<Primary><Alt>i",
+            scale_image,
+        ),
+    ] {
+        let action = gio::SimpleAction::new(name, None);
+        let window_clone = window.clone();
+        let viewport = viewport.clone();
+        let on_change = on_change.clone();
+        action.connect_activate(move |_, _| {
+            f(&window_clone, &viewport);
+            on_change();
+        });
+        window.add_action(&action);
+        let name = format!("win.{}", name);
+        application.set_accels_for_action(&name, &[accel]);
+        menu.append(Some(label), Some(&name));
+    }
+
+    let button = gtk::MenuButton::new();
+    button.set_label("Image");
+    button.set_can_focus(false);
+    button.set_menu_model(Some(&menu));
+    header.pack_start(&button);
+}
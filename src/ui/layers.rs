@@ -0,0 +1,166 @@
+use gtk::prelude::*;
+use std::cell::{Cell, RefCell};
+use std::rc::Rc;
+
+use super::viewport::Viewport;
+
+#[derive(Clone, PartialEq)]
+struct LayerState {
+    name: String,
+    visible: bool,
+    opacity: f64,
+}
+
+/// Side panel listing the layers from top to bottom.
+pub struct LayerPanel {
+    pub widget: gtk::Box,
+    list: gtk::ListBox,
+    viewport: Rc<RefCell<Viewport>>,
+    shown: RefCell<(Vec<LayerState>, usize)>,
+    updating: Cell<bool>,
+}
+
+impl LayerPanel {
+    pub fn new(viewport: Rc<RefCell<Viewport>>) -> Rc<LayerPanel> {
+        let widget = gtk::Box::new(gtk::Orientation::Vertical, 0);
+        let list = gtk::ListBox::new();
+        list.set_selection_mode(gtk::SelectionMode::Single);
+        list.set_can_focus(false);
+        let scrolled = gtk::ScrolledWindow::new(
+            None::<&gtk::Adjustment>,
+            None::<&gtk::Adjustment>,
+        );
+        scrolled.set_vexpand(true);
+        scrolled.set_size_request(220, -1);
+        scrolled.add(&list);
+        widget.add(&scrolled);
+        let buttons = gtk::Box::new(gtk::Orientation::Horizontal, 0);
+        widget.add(&buttons);
+
+        let panel = Rc::new(LayerPanel {
+            widget,
+            list,
+            viewport,
+            shown: RefCell::new((Vec::new(), 0)),
+            updating: Cell::new(false),
+        });
+
+        let panel_clone = panel.clone();
+        panel.list.connect_row_selected(move |_, row| {
+            if panel_clone.updating.get() {
+                return;
+            }
+            if let Some(row) = row {
+                let index = panel_clone.layer_index(row.get_index());
+                panel_clone.viewport.borrow_mut().set_active_layer(index);
+                panel_clone.shown.borrow_mut().1 = index;
+            }
+        });
+
+        let actions: [(&str, &str, fn(&mut Viewport)); 5] = [
+            ("list-add-symbolic", "New layer", |v| v.add_layer()),
+            ("edit-copy-symbolic", "Duplicate layer", |v| {
+                v.duplicate_layer()
+            }),
+            ("list-remove-symbolic", "Delete layer", |v| v.delete_layer()),
+            ("go-up-symbolic", "Raise layer", |v| v.move_layer(true)),
+            ("go-down-symbolic", "Lower layer", |v| v.move_layer(false)),
+        ];
+        for &(icon, tooltip, action) in &actions {
+            let button =
+                gtk::Button::from_icon_name(Some(icon), gtk::IconSize::Button);
+            button.set_tooltip_text(Some(tooltip));
+            button.set_can_focus(false);
+            let panel_clone = panel.clone();
+            button.connect_clicked(move |_| {
+                action(&mut panel_clone.viewport.borrow_mut());
+                panel_clone.refresh();
+            });
+            buttons.add(&button);
+        }
+
+        panel.refresh();
+        panel
+    }
+
+    fn layer_index(&self, row_index: i32) -> usize {
+        self.shown.borrow().0.len() - 1 - row_index as usize
+    }
+
+    /// Rebuilds the list if the layers differ from what is shown.
+    pub fn refresh(self: &Rc<Self>) {
+        let state = {
+            let viewport = self.viewport.borrow();
+            let layers = viewport
+                .layers()
+                .iter()
+                .map(|l| LayerState {
+                    name: l.name.clone(),
+                    visible: l.visible,
+                    opacity: l.opacity,
+                })
+                .collect::<Vec<_>>();
+            (layers, viewport.active_layer())
+        };
+        if *self.shown.borrow() == state {
+            return;
+        }
+        self.updating.set(true);
+        for child in self.list.get_children() {
+            self.list.remove(&child);
+        }
+        for (index, layer) in state.0.iter().enumerate().rev() {
+            self.list.add(&self.make_row(index, layer));
+        }
+        self.list.show_all();
+        let active_row = state.0.len() - 1 - state.1;
+        self.list
+            .select_row(self.list.get_row_at_index(active_row as i32).as_ref());
+        *self.shown.borrow_mut() = state;
+        self.updating.set(false);
+    }
+
+    fn make_row(self: &Rc<Self>, index: usize, layer: &LayerState) -> gtk::Box {
+        let row = gtk::Box::new(gtk::Orientation::Horizontal, 4);
+
+        let visible = gtk::CheckButton::new();
+        visible.set_active(layer.visible);
+        visible.set_can_focus(false);
+        visible.set_tooltip_text(Some("Visible"));
+        let panel = self.clone();
+        visible.connect_toggled(move |b| {
+            panel.shown.borrow_mut().0[index].visible = b.get_active();
+            panel
+                .viewport
+                .borrow_mut()
+                .set_layer_visible(index, b.get_active());
+        });
+        row.add(&visible);
+
+        let name = gtk::Label::new(Some(&layer.name));
+        name.set_xalign(0.0);
+        name.set_hexpand(true);
+        row.add(&name);
+
+        let opacity = gtk::Scale::with_range(
+            gtk::Orientation::Horizontal,
+            0.0,
+            100.0,
+            1.0,
+        );
+        opacity.set_value(layer.opacity * 100.0);
+        opacity.set_digits(0);
+        opacity.set_size_request(90, -1);
+        opacity.set_can_focus(false);
+        opacity.set_tooltip_text(Some("Opacity"));
+        let panel = self.clone();
+        opacity.connect_value_changed(move |s| {
+            let value = s.get_value() / 100.0;
+            panel.shown.borrow_mut().0[index].opacity = value;
+            panel.viewport.borrow_mut().set_layer_opacity(index, value);
+        });
+        row.add(&opacity);
+
+        row
+    }
+}
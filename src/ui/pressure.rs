@@ -0,0 +1,250 @@
+use gtk::prelude::*;
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use super::viewport::{PressureCurve, Viewport};
+
+/// Pen size in pixels on the scribble pad at full pressure.
+const PAD_PEN_SIZE: f64 = 8.0;
+
+/// A dialog to edit the pressure curve, with a pad to scribble on where the
+/// curve is applied live.
+struct PressurePanel {
+    dialog: gtk::Dialog,
+    pad: gtk::DrawingArea,
+    graph: gtk::DrawingArea,
+    gamma: gtk::Scale,
+    min_size: gtk::Scale,
+    opacity: gtk::CheckButton,
+    viewport: Rc<RefCell<Viewport>>,
+    /// Strokes drawn on the pad as (x, y, raw pressure).
+    strokes: RefCell<Vec<Vec<(f64, f64, f64)>>>,
+}
+
+fn draw_graph(
+    context: &cairo::Context,
+    (width, height): (f64, f64),
+    curve: PressureCurve,
+    last_pressure: Option<f64>,
+) {
+    let point = |p: f64, v: f64| (p * width, (1.0 - v) * height);
+    context.set_source_rgb(1.0, 1.0, 1.0);
+    context.paint();
+    context.set_line_width(1.0);
+    context.set_source_rgb(0.8, 0.8, 0.8);
+    context.move_to(0.0, height);
+    context.line_to(width, 0.0);
+    context.stroke();
+
+    let samples = 64;
+    let plot = |f: &dyn Fn(f64) -> f64| {
+        for i in 0..=samples {
+            let p = i as f64 / samples as f64;
+            let (x, y) = point(p, f(p));
+            context.line_to(x, y);
+        }
+        context.stroke();
+    };
+    context.set_line_width(2.0);
+    context.set_source_rgb(0.2, 0.4, 0.8);
+    plot(&|p| curve.size(p, 1.0));
+    if curve.opacity {
+        context.set_dash(&[4.0, 4.0], 0.0);
+        context.set_source_rgb(0.8, 0.4, 0.2);
+        plot(&|p| curve.opacity(p));
+        context.set_dash(&[], 0.0);
+    }
+
+    if let Some(p) = last_pressure {
+        let (x, y) = point(p, curve.size(p, 1.0));
+        context.set_source_rgb(0.0, 0.0, 0.0);
+        context.arc(x, y, 4.0, 0.0, 2.0 * std::f64::consts::PI);
+        context.fill();
+    }
+}
+
+fn draw_pad(
+    context: &cairo::Context,
+    strokes: &[Vec<(f64, f64, f64)>],
+    curve: PressureCurve,
+) {
+    context.set_source_rgb(1.0, 1.0, 1.0);
+    context.paint();
+    context.set_line_cap(cairo::LineCap::Round);
+    for stroke in strokes {
+        let segments = stroke.iter().zip(stroke.iter().skip(1));
+        for (&(x1, y1, _), &(x2, y2, p)) in segments {
+            context.set_source_rgba(0.0, 0.0, 0.0, curve.opacity(p));
+            context.set_line_width(2.0 * curve.size(p, PAD_PEN_SIZE));
+            context.move_to(x1, y1);
+            context.line_to(x2, y2);
+            context.stroke();
+        }
+    }
+}
+
+impl PressurePanel {
+    fn new(
+        window: &gtk::ApplicationWindow,
+        viewport: Rc<RefCell<Viewport>>,
+    ) -> Rc<PressurePanel> {
+        let dialog = gtk::Dialog::new();
+        dialog.set_title("Pressure Curve");
+        dialog.set_transient_for(Some(window));
+        dialog.connect_delete_event(|d, _| {
+            d.hide();
+            gtk::Inhibit(true)
+        });
+
+        let pad = gtk::DrawingArea::new();
+        pad.set_size_request(320, 200);
+        pad.add_events(gdk::EventMask::BUTTON_PRESS_MASK);
+        pad.add_events(gdk::EventMask::BUTTON1_MOTION_MASK);
+        let graph = gtk::DrawingArea::new();
+        graph.set_size_request(200, 200);
+
+        let curve = viewport.borrow().pressure_curve();
+        let gamma =
+            gtk::Scale::with_range(gtk::Orientation::Horizontal, 0.2, 5.0, 0.1);
+        gamma.set_value(curve.gamma);
+        let min_size = gtk::Scale::with_range(
+            gtk::Orientation::Horizontal,
+            0.0,
+            100.0,
+            1.0,
+        );
+        min_size.set_value(curve.min_size * 100.0);
+        min_size.set_digits(0);
+        let opacity =
+            gtk::CheckButton::with_label("Pressure changes the opacity");
+        opacity.set_active(curve.opacity);
+        let clear = gtk::Button::with_label("Clear");
+
+        let grid = gtk::Grid::new();
+        grid.set_row_spacing(4);
+        grid.set_column_spacing(8);
+        grid.set_border_width(8);
+        grid.attach(&graph, 0, 0, 1, 1);
+        grid.attach(&pad, 1, 0, 1, 1);
+        for (row, &(label, scale)) in
+            [("Gamma", &gamma), ("Minimum size %", &min_size)]
+                .iter()
+                .enumerate()
+        {
+            let label = gtk::Label::new(Some(label));
+            label.set_xalign(0.0);
+            grid.attach(&label, 0, row as i32 + 1, 1, 1);
+            grid.attach(scale, 1, row as i32 + 1, 1, 1);
+        }
+        grid.attach(&opacity, 0, 3, 1, 1);
+        grid.attach(&clear, 1, 3, 1, 1);
+        dialog.get_content_area().add(&grid);
+
+        let panel = Rc::new(PressurePanel {
+            dialog,
+            pad,
+            graph,
+            gamma,
+            min_size,
+            opacity,
+            viewport,
+            strokes: RefCell::new(Vec::new()),
+        });
+
+        for scale in &[&panel.gamma, &panel.min_size] {
+            let panel_clone = panel.clone();
+            scale.connect_value_changed(move |_| panel_clone.update_curve());
+        }
+        let panel_clone = panel.clone();
+        panel
+            .opacity
+            .connect_toggled(move |_| panel_clone.update_curve());
+        let panel_clone = panel.clone();
+        clear.connect_clicked(move |_| {
+            panel_clone.strokes.borrow_mut().clear();
+            panel_clone.queue_draw();
+        });
+
+        let panel_clone = panel.clone();
+        panel.pad.connect_button_press_event(move |_, e| {
+            panel_clone.strokes.borrow_mut().push(Vec::new());
+            panel_clone.add_point(
+                e.get_position(),
+                e.get_axis(gdk::AxisUse::Pressure),
+            );
+            gtk::Inhibit(true)
+        });
+        let panel_clone = panel.clone();
+        panel.pad.connect_motion_notify_event(move |_, e| {
+            panel_clone.add_point(
+                e.get_position(),
+                e.get_axis(gdk::AxisUse::Pressure),
+            );
+            gtk::Inhibit(true)
+        });
+
+        let panel_clone = panel.clone();
+        panel.pad.connect_draw(move |_, c| {
+            let curve = panel_clone.viewport.borrow().pressure_curve();
+            draw_pad(c, &panel_clone.strokes.borrow(), curve);
+            gtk::Inhibit(false)
+        });
+        let panel_clone = panel.clone();
+        panel.graph.connect_draw(move |w, c| {
+            let curve = panel_clone.viewport.borrow().pressure_curve();
+            let last_pressure = panel_clone
+                .strokes
+                .borrow()
+                .last()
+                .and_then(|s| s.last())
+                .map(|p| p.2);
+            let size = (
+                w.get_allocated_width() as f64,
+                w.get_allocated_height() as f64,
+            );
+            draw_graph(c, size, curve, last_pressure);
+            gtk::Inhibit(false)
+        });
+
+        panel
+    }
+
+    fn add_point(&self, (x, y): (f64, f64), pressure: Option<f64>) {
+        if let Some(stroke) = self.strokes.borrow_mut().last_mut() {
+            stroke.push((x, y, pressure.unwrap_or(super::DEFAULT_PRESSURE)));
+        }
+        self.queue_draw();
+    }
+
+    fn update_curve(&self) {
+        self.viewport
+            .borrow_mut()
+            .set_pressure_curve(PressureCurve {
+                gamma: self.gamma.get_value(),
+                min_size: self.min_size.get_value() / 100.0,
+                opacity: self.opacity.get_active(),
+            });
+        self.queue_draw();
+    }
+
+    fn queue_draw(&self) {
+        self.pad.queue_draw();
+        self.graph.queue_draw();
+    }
+}
+
+/// Adds a header button that opens the pressure curve dialog.
+pub fn add_pressure_button(
+    window: &gtk::ApplicationWindow,
+    header: &gtk::HeaderBar,
+    viewport: Rc<RefCell<Viewport>>,
+) {
+    let panel = PressurePanel::new(window, viewport);
+    let button = gtk::Button::with_label("Pressure");
+    button.set_can_focus(false);
+    button.connect_clicked(move |_| {
+        panel.dialog.show_all();
+        panel.dialog.present();
+    });
+    header.pack_end(&button);
+}
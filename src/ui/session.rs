@@ -0,0 +1,64 @@
+use std::cell::RefCell;
+use std::path::Path;
+use std::rc::Rc;
+use std::time::Instant;
+
+use super::viewport::Viewport;
+use simple_paint::session::{self, Recorder};
+
+/// Milliseconds between checks for replayed events that are due.
+const REPLAY_INTERVAL: u32 = 5;
+
+/// Records the input to the file named by `SIMPLE_PAINT_RECORD`, if set.
+pub fn start_recording(viewport: &Rc<RefCell<Viewport>>) {
+    let path = match std::env::var_os("SIMPLE_PAINT_RECORD") {
+        Some(p) => p,
+        None => return,
+    };
+    match Recorder::create(Path::new(&path)) {
+        Ok(r) => viewport.borrow_mut().set_recorder(Some(r)),
+        Err(e) => {
+            eprintln!("Cannot record to {}: {}", path.to_string_lossy(), e)
+        }
+    }
+}
+
+/// Replays the session recorded in the file named by `SIMPLE_PAINT_REPLAY`,
+/// if set, with the recorded timing. The replay starts once there is
+/// `context` to draw on. `on_change` is called after each batch of events.
+pub fn start_replay(
+    viewport: Rc<RefCell<Viewport>>,
+    context: Rc<RefCell<Option<cairo::Context>>>,
+    on_change: Box<dyn Fn()>,
+) {
+    let path = match std::env::var_os("SIMPLE_PAINT_REPLAY") {
+        Some(p) => p,
+        None => return,
+    };
+    let events = std::fs::read_to_string(&path)
+        .map_err(|e| e.to_string())
+        .and_then(|text| session::parse(&text).map_err(|e| e.to_string()));
+    let mut events = match events {
+        Ok(events) => events.into_iter().peekable(),
+        Err(e) => {
+            eprintln!("Cannot replay {}: {}", path.to_string_lossy(), e);
+            return;
+        }
+    };
+    let mut start = None;
+    glib::timeout_add_local(REPLAY_INTERVAL, move || {
+        if context.borrow().is_none() {
+            return glib::Continue(true);
+        }
+        let time = start.get_or_insert_with(Instant::now).elapsed();
+        let time = time.as_secs_f64();
+        {
+            let mut viewport = viewport.borrow_mut();
+            while let Some((_, e)) = events.next_if(|(t, _)| *t <= time) {
+                viewport.replay(&e);
+            }
+        }
+        on_change();
+        glib::Continue(events.peek().is_some())
+    });
+}
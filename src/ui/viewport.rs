@@ -1,8 +1,25 @@
-mod canvas;
+use glib::translate::FromGlib;
+use simple_paint::session::{Event, Recorder};
+use simple_paint::{canvas, png};
 
-pub use canvas::PenInput;
-use canvas::{Canvas, SingleVecImage, RGB};
-use std::{cell::RefCell, collections::HashSet, rc::Rc};
+use canvas::shape::{self, Shape};
+use canvas::{
+    Canvas, Floating, Placement, SelectionMode, SelectionShape, Stabilizer,
+    TiledImage,
+};
+pub use canvas::{
+    FillSetting, Filter, Layer, PenInput, PressureCurve, Resample,
+    StabilizerMode, DEFAULT_MEMORY_BUDGET, RGB,
+};
+use std::{
+    cell::RefCell,
+    collections::HashSet,
+    error::Error,
+    fs::File,
+    io::{BufReader, BufWriter, Write},
+    path::Path,
+    rc::Rc,
+};
 
 struct ViewportData {
     size: (usize, usize),
@@ -10,39 +27,326 @@ struct ViewportData {
     cairo_context: Rc<RefCell<Option<cairo::Context>>>,
     canvas_display_matrix: cairo::Matrix,
     image_surface: Option<cairo::ImageSurface>,
+    checkerboard: cairo::SurfacePattern,
+    /// Borders of the selection in canvas coordinates.
+    selection_outline: Vec<((f64, f64), (f64, f64))>,
+    /// Dash offset of the marching ants.
+    ants_offset: f64,
+    /// Area kept by the crop tool in canvas coordinates. The rest is dimmed.
+    crop_area: Option<canvas::Rectangle>,
+}
+
+/// The pattern shown behind transparent parts of the canvas.
+fn checkerboard() -> cairo::SurfacePattern {
+    let surface =
+        cairo::ImageSurface::create(cairo::Format::Rgb24, 16, 16).unwrap();
+    {
+        let context = cairo::Context::new(&surface);
+        context.set_source_rgb(0.8, 0.8, 0.8);
+        context.paint();
+        context.set_source_rgb(0.6, 0.6, 0.6);
+        context.rectangle(0.0, 0.0, 8.0, 8.0);
+        context.rectangle(8.0, 8.0, 8.0, 8.0);
+        context.fill();
+    }
+    let pattern = cairo::SurfacePattern::create(&surface);
+    pattern.set_extend(cairo::Extend::Repeat);
+    pattern
 }
 
 impl ViewportData {
+    /// Copies `area` of `image` to the surface shown on screen. The surface
+    /// is kept between calls and only made anew when the canvas size
+    /// changed, so the cost depends on the size of `area`.
+    fn upload_image(
+        &mut self,
+        image: &TiledImage,
+        canvas_size: (usize, usize),
+        area: &canvas::Rectangle,
+    ) {
+        let same_size = self.image_surface.as_ref().is_some_and(|s| {
+            (s.get_width() as usize, s.get_height() as usize) == canvas_size
+        });
+        if same_size {
+            let surface = self.image_surface.as_mut().unwrap();
+            let (xs, ys) = area.pixels(canvas_size);
+            let stride = surface.get_stride() as usize;
+            // Fails only while a drawing still refers to the surface.
+            if let Ok(mut data) = surface.get_data() {
+                image.copy_to(xs, ys, &mut data, stride);
+                return;
+            }
+        }
+        self.image_surface = Some(image_surface(image));
+    }
+
     fn render_image_surface(&self, area: canvas::Rectangle) -> Option<()> {
+        let image_surface = self.image_surface.as_ref()?;
         let context = self.cairo_context.borrow();
         let context = context.as_ref().unwrap();
         context.save();
         context.set_matrix(self.canvas_display_matrix);
         context.rectangle(area.x, area.y, area.width, area.height);
         context.clip();
-        context.set_source_surface(self.image_surface.as_ref()?, 0.0, 0.0);
-        let filter = if self
-            .canvas_display_matrix
-            .transform_distance(1.0, 0.0)
-            .0
-            > 1.5
-        {
+        context.identity_matrix();
+        context.set_source(&self.checkerboard);
+        context.paint();
+        context.set_matrix(self.canvas_display_matrix);
+        context.set_source_surface(image_surface, 0.0, 0.0);
+        let filter = if self.zoom() > 1.5 {
             cairo::Filter::Nearest
         } else {
             cairo::Filter::Good
         };
         context.get_source().set_filter(filter);
         context.paint();
+        if let Some(c) = &self.crop_area {
+            context.new_path();
+            context.set_fill_rule(cairo::FillRule::EvenOdd);
+            context.rectangle(
+                0.0,
+                0.0,
+                image_surface.get_width() as f64,
+                image_surface.get_height() as f64,
+            );
+            context.rectangle(c.x, c.y, c.width, c.height);
+            context.set_source_rgba(0.0, 0.0, 0.0, CROP_SHADE);
+            context.fill();
+        }
+        self.render_marching_ants(&area);
         context.restore();
+        if let Some(c) = &self.crop_area {
+            let handles = crop_handles(c);
+            let corners = [0, 2, 4, 6, 0].iter().map(|&i| handles[i].0);
+            self.render_outline(&corners.collect::<Vec<_>>());
+            let points = handles.iter().map(|h| h.0).collect::<Vec<_>>();
+            self.render_handles(&points);
+        }
         Some(())
     }
+
+    /// Draws the selection borders that cross `area`.
+    fn render_marching_ants(&self, area: &canvas::Rectangle) {
+        if self.selection_outline.is_empty() {
+            return;
+        }
+        let context = self.cairo_context.borrow();
+        let context = context.as_ref().unwrap();
+        context.new_path();
+        for &((x1, y1), (x2, y2)) in &self.selection_outline {
+            if x2 < area.x
+                || area.x + area.width < x1
+                || y2 < area.y
+                || area.y + area.height < y1
+            {
+                continue;
+            }
+            context.move_to(x1, y1);
+            context.line_to(x2, y2);
+        }
+        context.identity_matrix();
+        context.set_line_width(1.0);
+        context.set_source_rgb(1.0, 1.0, 1.0);
+        context.stroke_preserve();
+        context.set_source_rgb(0.0, 0.0, 0.0);
+        context.set_dash(&[4.0, 4.0], self.ants_offset);
+        context.stroke();
+    }
+
+    /// Draws square handles at `points` given in canvas coordinates.
+    fn render_handles(&self, points: &[(f64, f64)]) {
+        let context = self.cairo_context.borrow();
+        let context = context.as_ref().unwrap();
+        context.save();
+        context.new_path();
+        for &(x, y) in points {
+            let (x, y) = self.canvas_display_matrix.transform_point(x, y);
+            context.rectangle(
+                x - HANDLE_SIZE / 2.0,
+                y - HANDLE_SIZE / 2.0,
+                HANDLE_SIZE,
+                HANDLE_SIZE,
+            );
+        }
+        context.set_source_rgb(1.0, 1.0, 1.0);
+        context.fill_preserve();
+        context.set_line_width(1.0);
+        context.set_source_rgb(0.0, 0.0, 0.0);
+        context.stroke();
+        context.restore();
+    }
+
+    fn zoom(&self) -> f64 {
+        let (sx, sy) = self.canvas_display_matrix.transform_distance(1.0, 0.0);
+        sx.hypot(sy)
+    }
+
+    /// Draws a dashed polyline given in canvas coordinates.
+    fn render_outline(&self, points: &[(f64, f64)]) {
+        let context = self.cairo_context.borrow();
+        let context = context.as_ref().unwrap();
+        context.save();
+        context.new_path();
+        context.set_matrix(self.canvas_display_matrix);
+        for &(x, y) in points {
+            context.line_to(x, y);
+        }
+        context.identity_matrix();
+        context.set_line_width(1.0);
+        context.set_source_rgb(1.0, 1.0, 1.0);
+        context.stroke_preserve();
+        context.set_source_rgb(0.0, 0.0, 0.0);
+        context.set_dash(&[4.0, 4.0], 0.0);
+        context.stroke();
+        context.restore();
+    }
+}
+
+/// Distance in viewport pixels within which a click closes a polygon.
+const POLYGON_CLOSE_DISTANCE: f64 = 8.0;
+
+const DEFAULT_CANVAS_SIZE: (usize, usize) = (2000, 1000);
+
+/// Size in viewport pixels of the handles of the transform and crop tools.
+const HANDLE_SIZE: f64 = 8.0;
+
+/// Opacity of the black laid over the area removed by cropping.
+const CROP_SHADE: f64 = 0.5;
+
+/// Handles of the crop area at its corners and the middles of its sides,
+/// clockwise from the top left. Each comes with the sides it moves as left,
+/// top, right and bottom.
+fn crop_handles(a: &canvas::Rectangle) -> [((f64, f64), [bool; 4]); 8] {
+    let (x0, y0, x1, y1) = (a.x, a.y, a.x + a.width, a.y + a.height);
+    let (xm, ym) = ((x0 + x1) / 2.0, (y0 + y1) / 2.0);
+    [
+        ((x0, y0), [true, true, false, false]),
+        ((xm, y0), [false, true, false, false]),
+        ((x1, y0), [false, true, true, false]),
+        ((x1, ym), [false, false, true, false]),
+        ((x1, y1), [false, false, true, true]),
+        ((xm, y1), [false, false, false, true]),
+        ((x0, y1), [true, false, false, true]),
+        ((x0, ym), [true, false, false, false]),
+    ]
+}
+
+/// A drag of the crop tool, in canvas coordinates.
+#[derive(Debug, Clone, Copy)]
+struct CropDrag {
+    /// Sides moved as left, top, right and bottom. All of them move the
+    /// whole area.
+    sides: [bool; 4],
+    start: (f64, f64),
+    area: canvas::Rectangle,
+}
+
+impl CropDrag {
+    /// Returns the area after dragging to `point`, snapped to pixels and
+    /// kept on a canvas of `size`.
+    fn area(
+        &self,
+        point: (f64, f64),
+        size: (usize, usize),
+    ) -> canvas::Rectangle {
+        let a = self.area;
+        let (w, h) = (size.0 as f64, size.1 as f64);
+        let (mut dx, mut dy) = (point.0 - self.start.0, point.1 - self.start.1);
+        if self.sides == [true; 4] {
+            dx = dx.clamp(-a.x, (w - a.x - a.width).max(-a.x));
+            dy = dy.clamp(-a.y, (h - a.y - a.height).max(-a.y));
+        }
+        let [left, top, right, bottom] = self.sides;
+        let moved = |side: bool, d: f64| if side { d } else { 0.0 };
+        let x0 = a.x + moved(left, dx);
+        let x1 = a.x + a.width + moved(right, dx);
+        let y0 = a.y + moved(top, dy);
+        let y1 = a.y + a.height + moved(bottom, dy);
+        let snap = |v: f64, max: f64| v.round().clamp(0.0, max);
+        let (x0, x1) = (snap(x0.min(x1), w), snap(x0.max(x1), w));
+        let (y0, y1) = (snap(y0.min(y1), h), snap(y0.max(y1), h));
+        canvas::Rectangle {
+            x: x0,
+            y: y0,
+            width: x1 - x0,
+            height: y1 - y0,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum TransformHandle {
+    Move,
+    /// Scales by dragging the corner with this index.
+    Scale(usize),
+    Rotate,
+}
+
+/// A drag of the transform tool, in canvas coordinates.
+#[derive(Debug, Clone, Copy)]
+struct TransformDrag {
+    handle: TransformHandle,
+    start: (f64, f64),
+    placement: Placement,
+    center: (f64, f64),
+}
+
+impl TransformDrag {
+    /// Returns the placement after dragging to `point`. `constrain` keeps
+    /// the aspect ratio or snaps the angle to 15 degrees.
+    fn placement(&self, point: (f64, f64), constrain: bool) -> Placement {
+        let mut p = self.placement;
+        let (cx, cy) = self.center;
+        let from = (self.start.0 - cx, self.start.1 - cy);
+        let to = (point.0 - cx, point.1 - cy);
+        match self.handle {
+            TransformHandle::Move => {
+                p.offset.0 += point.0 - self.start.0;
+                p.offset.1 += point.1 - self.start.1;
+            }
+            TransformHandle::Rotate => {
+                p.angle += to.1.atan2(to.0) - from.1.atan2(from.0);
+                if constrain {
+                    let step = std::f64::consts::PI / 12.0;
+                    p.angle = (p.angle / step).round() * step;
+                }
+            }
+            TransformHandle::Scale(_) => {
+                let (sin, cos) = p.angle.sin_cos();
+                let unrotate = |(x, y): (f64, f64)| {
+                    (x * cos + y * sin, -x * sin + y * cos)
+                };
+                let (from, to) = (unrotate(from), unrotate(to));
+                if constrain {
+                    let k = to.0.hypot(to.1) / from.0.hypot(from.1).max(1e-9);
+                    p.scale = (p.scale.0 * k, p.scale.1 * k);
+                } else {
+                    if from.0.abs() > 1e-9 {
+                        p.scale.0 *= to.0 / from.0;
+                    }
+                    if from.1.abs() > 1e-9 {
+                        p.scale.1 *= to.1 / from.1;
+                    }
+                }
+            }
+        }
+        p
+    }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy, PartialEq)]
 enum PenKind {
     PanCanvas,
     Circle,
+    Eraser,
     Zoom,
+    RotateView,
+    Shape(Shape),
+    Fill,
+    Eyedropper,
+    Select(SelectionShape),
+    Transform,
+    Crop,
 }
 
 pub struct Viewport {
@@ -50,31 +354,57 @@ pub struct Viewport {
     canvas: Canvas,
     draw_handler: Box<dyn Fn()>,
     pen_kind: PenKind,
+    /// The pen used while no navigation key is held.
+    selected_pen_kind: PenKind,
+    eraser_tip: bool,
     previous_input: Option<PenInput>,
     pressing_keys: HashSet<gdk::keys::Key>,
     stroke_start_position: Option<(f64, f64)>,
+    stabilizer: Stabilizer,
+    /// Start of the shape being dragged, or the vertices of a polygon, in
+    /// canvas coordinates.
+    shape_points: Vec<(f64, f64)>,
+    shape_end: Option<(f64, f64)>,
+    shape_pressure: f64,
+    /// Width in pixels of the square averaged by the eyedropper.
+    eyedropper_size: usize,
+    /// Whether the eyedropper samples all layers or only the active one.
+    eyedropper_merged: bool,
+    /// Points dragged through by a selection tool, in canvas coordinates.
+    selection_points: Vec<(f64, f64)>,
+    selection_mode: SelectionMode,
+    transform_drag: Option<TransformDrag>,
+    crop_drag: Option<CropDrag>,
+    /// Writes the input to a file if recording.
+    recorder: Option<Recorder>,
+}
+
+fn image_surface(image: &TiledImage) -> cairo::ImageSurface {
+    let mut surface = cairo::ImageSurface::create(
+        cairo::Format::ARgb32,
+        image.width as i32,
+        image.height as i32,
+    )
+    .unwrap();
+    let stride = surface.get_stride() as usize;
+    image.copy_to(
+        0..image.width,
+        0..image.height,
+        &mut surface.get_data().unwrap(),
+        stride,
+    );
+    surface
 }
 
 fn make_draw_handler(
     viewport_data: Rc<RefCell<ViewportData>>,
 ) -> canvas::DrawHandler {
     Box::new(
-        move |image: &SingleVecImage,
+        move |image: &TiledImage,
               canvas_size: (usize, usize),
               changed_area: canvas::Rectangle| {
-            let stride = cairo::Format::Rgb24
-                .stride_for_width(image.width as u32)
-                .unwrap();
-            let image = cairo::ImageSurface::create_for_data(
-                image.vector.clone(),
-                cairo::Format::Rgb24,
-                canvas_size.0 as i32,
-                canvas_size.1 as i32,
-                stride,
-            )
-            .unwrap();
             let mut viewport_data_mu = viewport_data.borrow_mut();
-            viewport_data_mu.image_surface = Some(image);
+            viewport_data_mu.upload_image(image, canvas_size, &changed_area);
             viewport_data_mu.render_image_surface(changed_area);
         },
     )
@@ -86,22 +416,39 @@ impl Viewport {
         size: (usize, usize),
         draw_handler: Box<dyn Fn()>,
     ) -> Viewport {
-        let canvas_size = (2000, 1000);
+        let canvas_size = DEFAULT_CANVAS_SIZE;
         let data = Rc::new(RefCell::new(ViewportData {
             size,
             background_color: RGB::new(0x33, 0x33, 0x40),
             cairo_context,
             canvas_display_matrix: cairo::Matrix::identity(),
             image_surface: None,
+            checkerboard: checkerboard(),
+            selection_outline: Vec::new(),
+            ants_offset: 0.0,
+            crop_area: None,
         }));
         Viewport {
             data: data.clone(),
             canvas: Canvas::new(make_draw_handler(data), canvas_size),
             draw_handler,
             pen_kind: PenKind::Circle,
+            selected_pen_kind: PenKind::Circle,
+            eraser_tip: false,
             previous_input: None,
             pressing_keys: HashSet::new(),
             stroke_start_position: None,
+            stabilizer: Stabilizer::new(),
+            shape_points: Vec::new(),
+            shape_end: None,
+            shape_pressure: 1.0,
+            eyedropper_size: 1,
+            eyedropper_merged: true,
+            selection_points: Vec::new(),
+            selection_mode: SelectionMode::Replace,
+            transform_drag: None,
+            crop_drag: None,
+            recorder: None,
         }
     }
 
@@ -127,10 +474,14 @@ impl Viewport {
     }
 
     pub fn pen_stroke(&mut self, input: PenInput) {
+        self.record(Event::Pen(input));
         let adjusted_input = self.apply_inv_matrix(input);
         match self.pen_kind {
-            PenKind::Circle => {
-                self.canvas.pen_stroke(adjusted_input);
+            PenKind::Circle | PenKind::Eraser => {
+                self.canvas.set_eraser(self.pen_kind == PenKind::Eraser);
+                for i in self.stabilizer.push(input) {
+                    self.stroke_canvas(i);
+                }
             }
             PenKind::PanCanvas => {
                 if let Some(i) = self.previous_input {
@@ -160,6 +511,93 @@ impl Viewport {
                     );
                 }
             }
+            PenKind::RotateView => {
+                if let Some(previous) = self.previous_input {
+                    let (cx, cy) = self.viewport_center();
+                    let angle = |i: PenInput| (i.y - cy).atan2(i.x - cx);
+                    self.rotate_view(angle(input) - angle(previous));
+                }
+            }
+            PenKind::Shape(shape) => {
+                let point = (adjusted_input.x, adjusted_input.y);
+                let start = match self.shape_points.last() {
+                    Some(&p) => p,
+                    None => {
+                        self.shape_points.push(point);
+                        self.shape_pressure = input.pressure;
+                        point
+                    }
+                };
+                self.shape_end = Some(if self.shift_pressed() {
+                    shape::constrain(shape, start, point)
+                } else {
+                    point
+                });
+                self.render_shape(shape);
+            }
+            PenKind::Fill => {
+                let PenInput { x, y, .. } = adjusted_input;
+                if self.previous_input.is_none() && x >= 0.0 && y >= 0.0 {
+                    self.canvas.fill(x as usize, y as usize);
+                }
+            }
+            PenKind::Eyedropper => {
+                if let Some(c) = self.canvas.pick_color(
+                    adjusted_input.x,
+                    adjusted_input.y,
+                    self.eyedropper_size,
+                    self.eyedropper_merged,
+                ) {
+                    let (_, background) = self.canvas.pen_colors();
+                    self.canvas.set_pen_colors(c, background);
+                }
+            }
+            PenKind::Select(shape) => {
+                if self.selection_points.is_empty() {
+                    self.selection_mode =
+                        match (self.shift_pressed(), self.control_pressed()) {
+                            (true, true) => SelectionMode::Intersect,
+                            (true, false) => SelectionMode::Add,
+                            (false, true) => SelectionMode::Subtract,
+                            (false, false) => SelectionMode::Replace,
+                        };
+                }
+                if shape != SelectionShape::Lasso {
+                    self.selection_points.truncate(1);
+                }
+                self.selection_points
+                    .push((adjusted_input.x, adjusted_input.y));
+                self.render_selection_drag(shape);
+            }
+            PenKind::Crop => {
+                let point = (adjusted_input.x, adjusted_input.y);
+                if self.previous_input.is_none() {
+                    self.crop_drag = Some(self.crop_drag_at(input, point));
+                }
+                if let Some(drag) = self.crop_drag {
+                    let area = drag.area(point, self.canvas.get_size());
+                    self.data.borrow_mut().crop_area = Some(area);
+                    self.render_view();
+                }
+            }
+            PenKind::Transform => {
+                let point = (adjusted_input.x, adjusted_input.y);
+                if self.previous_input.is_none() {
+                    self.begin_transform();
+                    self.transform_drag =
+                        self.canvas.floating().map(|f| TransformDrag {
+                            handle: self.transform_handle(f, input),
+                            start: point,
+                            placement: f.placement,
+                            center: f.center(),
+                        });
+                }
+                if let Some(drag) = self.transform_drag {
+                    let placement = drag.placement(point, self.shift_pressed());
+                    self.canvas.set_placement(placement);
+                    self.render_transform();
+                }
+            }
         }
         (self.draw_handler)();
         if self.previous_input.is_none() {
@@ -170,11 +608,332 @@ impl Viewport {
     }
 
     pub fn pen_stroke_end(&mut self) {
+        self.record(Event::StrokeEnd);
+        self.finish_stroke();
+    }
+
+    /// Ends the stroke without recording it, for the actions that imply it.
+    fn finish_stroke(&mut self) {
+        match self.pen_kind {
+            PenKind::Shape(shape) => self.end_shape_drag(shape),
+            PenKind::Select(shape) => self.end_selection_drag(shape),
+            _ => (),
+        }
+        self.transform_drag = None;
+        self.crop_drag = None;
+        let rest = self.stabilizer.finish();
+        if !rest.is_empty() {
+            for i in rest {
+                self.stroke_canvas(i);
+            }
+            (self.draw_handler)();
+        }
         self.previous_input = None;
         self.stroke_start_position = None;
         self.canvas.pen_stroke_end()
     }
 
+    fn end_shape_drag(&mut self, shape: Shape) {
+        let end = match self.shape_end.take() {
+            Some(end) => end,
+            None => return,
+        };
+        let first = self.shape_points[0];
+        if shape != Shape::Polygon {
+            self.commit_shape(shape::outline(shape, first, end));
+            return;
+        }
+        let distance = (end.0 - first.0).hypot(end.1 - first.1);
+        if self.shape_points.len() >= 3
+            && distance * self.data.borrow().zoom() < POLYGON_CLOSE_DISTANCE
+        {
+            self.finish_polygon();
+        } else {
+            if self.shape_points.last() != Some(&end) {
+                self.shape_points.push(end);
+            }
+            self.render_shape(shape);
+            (self.draw_handler)();
+        }
+    }
+
+    fn end_selection_drag(&mut self, shape: SelectionShape) {
+        if self.selection_points.is_empty() {
+            return;
+        }
+        let points = std::mem::take(&mut self.selection_points);
+        self.canvas
+            .select(&shape.polygon(&points), self.selection_mode);
+        self.update_selection_outline();
+    }
+
+    /// Shows the borders of the current selection.
+    fn update_selection_outline(&mut self) {
+        self.data.borrow_mut().selection_outline = self
+            .canvas
+            .selection()
+            .map_or_else(Vec::new, |s| s.outline());
+        self.render_view();
+        (self.draw_handler)();
+    }
+
+    /// Moves the marching ants of the selection by one step.
+    pub fn march_ants(&mut self) {
+        {
+            let mut data = self.data.borrow_mut();
+            if data.selection_outline.is_empty() {
+                return;
+            }
+            data.ants_offset = (data.ants_offset + 1.0) % 8.0;
+        }
+        self.render_overlay();
+        (self.draw_handler)();
+    }
+
+    /// Renders the view with the outline of the shape, selection or
+    /// transformation in progress, if any.
+    fn render_overlay(&self) {
+        match self.pen_kind {
+            PenKind::Shape(shape) if !self.shape_points.is_empty() => {
+                self.render_shape(shape)
+            }
+            PenKind::Select(shape) if !self.selection_points.is_empty() => {
+                self.render_selection_drag(shape)
+            }
+            _ if self.canvas.floating().is_some() => self.render_transform(),
+            _ => self.render_view(),
+        }
+    }
+
+    /// Renders the view with the outline of the selection being dragged.
+    fn render_selection_drag(&self, shape: SelectionShape) {
+        let mut polygon = shape.polygon(&self.selection_points);
+        polygon.extend(polygon.first().copied());
+        self.render_view();
+        self.data.borrow().render_outline(&polygon);
+    }
+
+    /// Lifts the selection or the active layer to transform it.
+    fn begin_transform(&mut self) {
+        if self.canvas.floating().is_none() {
+            self.canvas.begin_transform();
+            self.update_selection_outline();
+            self.render_transform();
+        }
+    }
+
+    /// Tells which part of the transform box is at `input`, given in
+    /// viewport coordinates.
+    fn transform_handle(
+        &self,
+        floating: &Floating,
+        input: PenInput,
+    ) -> TransformHandle {
+        let m = self.data.borrow().canvas_display_matrix;
+        let corners = floating
+            .corners()
+            .iter()
+            .map(|&(x, y)| m.transform_point(x, y))
+            .collect::<Vec<_>>();
+        if let Some(i) = corners.iter().position(|&(x, y)| {
+            (x - input.x).abs() <= HANDLE_SIZE
+                && (y - input.y).abs() <= HANDLE_SIZE
+        }) {
+            return TransformHandle::Scale(i);
+        }
+        let sides = (0..4).map(|i| {
+            let (x1, y1) = corners[i];
+            let (x2, y2) = corners[(i + 1) % 4];
+            ((x2 - x1) * (input.y - y1) - (y2 - y1) * (input.x - x1)).signum()
+        });
+        let sides = sides.collect::<Vec<_>>();
+        if sides.iter().all(|s| *s == sides[0]) {
+            TransformHandle::Move
+        } else {
+            TransformHandle::Rotate
+        }
+    }
+
+    /// Renders the view with the box and handles of the floating pixels.
+    fn render_transform(&self) {
+        self.render_view();
+        if let Some(f) = self.canvas.floating() {
+            let corners = f.corners();
+            let data = self.data.borrow();
+            data.render_outline(&[
+                corners[0], corners[1], corners[2], corners[3], corners[0],
+            ]);
+            data.render_handles(&corners);
+        }
+    }
+
+    /// Applies or cancels the transformation in progress.
+    fn end_transform(&mut self, apply: bool) {
+        if self.canvas.floating().is_some() {
+            if apply {
+                self.canvas.apply_transform();
+            } else {
+                self.canvas.cancel_transform();
+            }
+            self.render_view();
+            (self.draw_handler)();
+        }
+    }
+
+    /// Flips the floating pixels horizontally or vertically.
+    fn flip_floating(&mut self, horizontal: bool) {
+        if let Some(f) = self.canvas.floating() {
+            let mut p = f.placement;
+            if horizontal {
+                p.scale.0 = -p.scale.0;
+            } else {
+                p.scale.1 = -p.scale.1;
+            }
+            self.canvas.set_placement(p);
+            self.render_transform();
+            (self.draw_handler)();
+        }
+    }
+
+    /// Starts a drag of the crop tool at `input` in viewport coordinates,
+    /// which is `point` on the canvas.
+    fn crop_drag_at(&self, input: PenInput, point: (f64, f64)) -> CropDrag {
+        let new_area = CropDrag {
+            sides: [false, false, true, true],
+            start: point,
+            area: canvas::Rectangle {
+                x: point.0,
+                y: point.1,
+                width: 0.0,
+                height: 0.0,
+            },
+        };
+        let data = self.data.borrow();
+        let area = match data.crop_area {
+            Some(area) => area,
+            None => return new_area,
+        };
+        let m = data.canvas_display_matrix;
+        let handle = crop_handles(&area).iter().find_map(|&((x, y), sides)| {
+            let (x, y) = m.transform_point(x, y);
+            if (x - input.x).abs() <= HANDLE_SIZE
+                && (y - input.y).abs() <= HANDLE_SIZE
+            {
+                Some(sides)
+            } else {
+                None
+            }
+        });
+        let inside = area.x <= point.0
+            && point.0 <= area.x + area.width
+            && area.y <= point.1
+            && point.1 <= area.y + area.height;
+        let sides = match (handle, inside) {
+            (Some(sides), _) => sides,
+            (None, true) => [true; 4],
+            (None, false) => return new_area,
+        };
+        CropDrag {
+            sides,
+            start: point,
+            area,
+        }
+    }
+
+    /// Shows the crop area, starting with the whole canvas.
+    fn begin_crop(&mut self) {
+        let (width, height) = self.canvas.get_size();
+        self.data.borrow_mut().crop_area = Some(canvas::Rectangle {
+            x: 0.0,
+            y: 0.0,
+            width: width as f64,
+            height: height as f64,
+        });
+        self.render_view();
+        (self.draw_handler)();
+    }
+
+    /// Crops the canvas to the crop area or only hides the area. The view is
+    /// moved so that the kept pixels stay where they are on the screen.
+    fn end_crop(&mut self, apply: bool) {
+        let area = match self.data.borrow_mut().crop_area.take() {
+            Some(area) => area,
+            None => return,
+        };
+        if apply && area.width >= 1.0 && area.height >= 1.0 {
+            self.canvas.crop(area);
+            self.data
+                .borrow_mut()
+                .canvas_display_matrix
+                .translate(area.x, area.y);
+        }
+        self.update_selection_outline();
+    }
+
+    fn finish_polygon(&mut self) {
+        if self.shape_points.len() >= 2 {
+            let mut points = std::mem::take(&mut self.shape_points);
+            points.push(points[0]);
+            self.commit_shape(points);
+        }
+    }
+
+    /// Rasterizes the outline with the current pen.
+    fn commit_shape(&mut self, points: Vec<(f64, f64)>) {
+        self.cancel_shape();
+        self.canvas.draw_polyline(&points, self.shape_pressure);
+        self.follow_canvas_growth();
+        (self.draw_handler)();
+    }
+
+    /// Draws on the canvas at `input` given in viewport coordinates.
+    fn stroke_canvas(&mut self, input: PenInput) {
+        let input = self.apply_inv_matrix(input);
+        self.canvas.pen_stroke(input);
+        self.follow_canvas_growth();
+    }
+
+    /// Moves the view along with the origin of the canvas after it grew or
+    /// shrank, so that the pixels stay where they are on the screen.
+    fn follow_canvas_growth(&mut self) {
+        if let Some((dx, dy)) = self.canvas.take_growth() {
+            self.data
+                .borrow_mut()
+                .canvas_display_matrix
+                .translate(-dx as f64, -dy as f64);
+            self.render_view();
+        }
+    }
+
+    fn cancel_shape(&mut self) {
+        self.shape_points.clear();
+        self.shape_end = None;
+        self.render_view();
+    }
+
+    /// Renders the view with the outline of the shape in progress.
+    fn render_shape(&self, shape: Shape) {
+        let points = match (shape, self.shape_end) {
+            (Shape::Polygon, end) => {
+                self.shape_points.iter().copied().chain(end).collect()
+            }
+            (_, Some(end)) => shape::outline(shape, self.shape_points[0], end),
+            (_, None) => Vec::new(),
+        };
+        self.render_view();
+        self.data.borrow().render_outline(&points);
+    }
+
+    fn render_view(&self) {
+        self.clear();
+        self.data.borrow().render_image_surface(canvas::Rectangle {
+            x: 0.0,
+            y: 0.0,
+            width: self.canvas.canvas_size.0 as f64,
+            height: self.canvas.canvas_size.1 as f64,
+        });
+    }
+
     pub fn reflect_all(&mut self) {
         self.clear();
         self.canvas.reflect_all();
@@ -182,6 +941,7 @@ impl Viewport {
     }
 
     pub fn set_viewport_size(&mut self, width: usize, height: usize) {
+        self.record(Event::Resize(width, height));
         {
             let mut data = self.data.borrow_mut();
             data.size = (width, height);
@@ -201,7 +961,66 @@ impl Viewport {
         });
     }
 
+    fn viewport_center(&self) -> (f64, f64) {
+        let size = self.data.borrow().size;
+        (size.0 as f64 / 2.0, size.1 as f64 / 2.0)
+    }
+
+    /// Applies `transform`, given in viewport coordinates, after the current
+    /// display matrix.
+    fn transform_view(&mut self, transform: cairo::Matrix) {
+        self.clear();
+        let mut data = self.data.borrow_mut();
+        data.canvas_display_matrix =
+            cairo::Matrix::multiply(&data.canvas_display_matrix, &transform);
+        data.render_image_surface(canvas::Rectangle {
+            x: 0.0,
+            y: 0.0,
+            width: self.canvas.canvas_size.0 as f64,
+            height: self.canvas.canvas_size.1 as f64,
+        });
+    }
+
+    /// Rotates the view by `angle` radians around the viewport center.
+    fn rotate_view(&mut self, angle: f64) {
+        let (cx, cy) = self.viewport_center();
+        let mut m = cairo::Matrix::identity();
+        m.translate(cx, cy);
+        m.rotate(angle);
+        m.translate(-cx, -cy);
+        self.transform_view(m);
+    }
+
+    /// Mirrors the view horizontally around the viewport center.
+    fn flip_view(&mut self) {
+        let (cx, _) = self.viewport_center();
+        self.transform_view(cairo::Matrix::new(
+            -1.0,
+            0.0,
+            0.0,
+            1.0,
+            2.0 * cx,
+            0.0,
+        ));
+    }
+
+    /// Returns the angle by which the canvas is displayed rotated, measured
+    /// in the unflipped orientation.
+    fn view_rotation(&self) -> f64 {
+        let m = self.data.borrow().canvas_display_matrix;
+        if m.xx * m.yy - m.xy * m.yx < 0.0 {
+            (-m.yx).atan2(-m.xx)
+        } else {
+            m.yx.atan2(m.xx)
+        }
+    }
+
     pub fn set_canvas_center(&mut self) {
+        self.record(Event::CenterCanvas);
+        self.center_canvas();
+    }
+
+    fn center_canvas(&mut self) {
         let size = self.data.borrow().size;
         let canvas_width = self.canvas.get_size().0 as f64;
         let canvas_height = self.canvas.get_size().1 as f64;
@@ -225,14 +1044,174 @@ impl Viewport {
         });
     }
 
+    /// Zooms by `ds` keeping the canvas point shown at `position` in
+    /// viewport coordinates in place.
+    pub fn zoom_at(&mut self, position: (f64, f64), ds: f64) {
+        self.record(Event::Zoom(position, ds));
+        self.zoom(position, ds);
+    }
+
+    fn zoom(&mut self, position: (f64, f64), ds: f64) {
+        let origin = self.apply_inv_matrix(PenInput {
+            x: position.0,
+            y: position.1,
+            pressure: 0.0,
+        });
+        self.zoom_canvas_relative(ds, (origin.x, origin.y));
+        (self.draw_handler)();
+    }
+
+    fn zoom_at_viewport_center(&mut self, ds: f64) {
+        let size = self.data.borrow().size;
+        self.zoom((size.0 as f64 / 2.0, size.1 as f64 / 2.0), ds);
+    }
+
+    /// Moves the canvas by (`dx`, `dy`) in viewport coordinates.
+    pub fn scroll(&mut self, dx: f64, dy: f64) {
+        self.record(Event::Scroll(dx, dy));
+        let mut m = self.data.borrow().canvas_display_matrix;
+        m.invert();
+        let (dx, dy) = m.transform_distance(dx, dy);
+        self.move_canvas_relative(dx, dy);
+        (self.draw_handler)();
+    }
+
     pub fn key_press(&mut self, key: gdk::keys::Key) {
+        self.record(Event::KeyPress(*key));
         match key {
             gdk::keys::constants::KP_Add => {
-                self.zoom_canvas_relative(1.5, (0.0, 0.0));
-                (self.draw_handler)();
+                self.zoom_at_viewport_center(1.5);
             }
             gdk::keys::constants::KP_Subtract => {
-                self.zoom_canvas_relative(2.0 / 3.0, (0.0, 0.0));
+                self.zoom_at_viewport_center(2.0 / 3.0);
+            }
+            gdk::keys::constants::z if self.control_pressed() => {
+                self.end_transform(true);
+                self.canvas.undo();
+                self.follow_canvas_growth();
+                // Undoing a resize changes the canvas size.
+                self.update_selection_outline();
+            }
+            gdk::keys::constants::Z if self.control_pressed() => {
+                self.end_transform(true);
+                self.canvas.redo();
+                self.follow_canvas_growth();
+                self.update_selection_outline();
+            }
+            gdk::keys::constants::x if self.pressing_keys.is_empty() => {
+                self.canvas.swap_pen_colors();
+            }
+            gdk::keys::constants::_4 if self.pressing_keys.is_empty() => {
+                self.rotate_view(-std::f64::consts::PI / 12.0);
+                (self.draw_handler)();
+            }
+            gdk::keys::constants::_6 if self.pressing_keys.is_empty() => {
+                self.rotate_view(std::f64::consts::PI / 12.0);
+                (self.draw_handler)();
+            }
+            gdk::keys::constants::_5 if self.pressing_keys.is_empty() => {
+                self.rotate_view(-self.view_rotation());
+                (self.draw_handler)();
+            }
+            gdk::keys::constants::m if self.pressing_keys.is_empty() => {
+                self.flip_view();
+                (self.draw_handler)();
+            }
+            gdk::keys::constants::e if self.pressing_keys.is_empty() => {
+                self.select_pen(if self.selected_pen_kind == PenKind::Eraser {
+                    PenKind::Circle
+                } else {
+                    PenKind::Eraser
+                });
+            }
+            gdk::keys::constants::b if self.pressing_keys.is_empty() => {
+                self.select_pen(PenKind::Circle);
+            }
+            gdk::keys::constants::l if self.pressing_keys.is_empty() => {
+                self.select_pen(PenKind::Shape(Shape::Line));
+            }
+            gdk::keys::constants::r if self.pressing_keys.is_empty() => {
+                self.select_pen(PenKind::Shape(Shape::Rectangle));
+            }
+            gdk::keys::constants::o if self.pressing_keys.is_empty() => {
+                self.select_pen(PenKind::Shape(Shape::Ellipse));
+            }
+            gdk::keys::constants::p if self.pressing_keys.is_empty() => {
+                self.select_pen(PenKind::Shape(Shape::Polygon));
+            }
+            gdk::keys::constants::s if self.pressing_keys.is_empty() => {
+                let shape = match self.selected_pen_kind {
+                    PenKind::Select(SelectionShape::Rectangle) => {
+                        SelectionShape::Ellipse
+                    }
+                    PenKind::Select(SelectionShape::Ellipse) => {
+                        SelectionShape::Lasso
+                    }
+                    _ => SelectionShape::Rectangle,
+                };
+                self.select_pen(PenKind::Select(shape));
+            }
+            gdk::keys::constants::a if self.control_pressed() => {
+                self.canvas.select_all();
+                self.update_selection_outline();
+            }
+            gdk::keys::constants::d if self.control_pressed() => {
+                self.canvas.set_selection(None);
+                self.update_selection_outline();
+            }
+            gdk::keys::constants::i if self.control_pressed() => {
+                self.canvas.invert_selection();
+                self.update_selection_outline();
+            }
+            gdk::keys::constants::f if self.pressing_keys.is_empty() => {
+                self.select_pen(PenKind::Fill);
+            }
+            gdk::keys::constants::c if self.pressing_keys.is_empty() => {
+                self.select_pen(PenKind::Crop);
+                self.begin_crop();
+            }
+            gdk::keys::constants::Return
+                if self.data.borrow().crop_area.is_some() =>
+            {
+                self.end_crop(true);
+            }
+            gdk::keys::constants::Escape
+                if self.data.borrow().crop_area.is_some() =>
+            {
+                self.end_crop(false);
+            }
+            gdk::keys::constants::t if self.pressing_keys.is_empty() => {
+                self.select_pen(PenKind::Transform);
+                self.begin_transform();
+                (self.draw_handler)();
+            }
+            gdk::keys::constants::h
+                if self.pen_kind == PenKind::Transform
+                    && self.pressing_keys.is_empty() =>
+            {
+                self.flip_floating(true);
+            }
+            gdk::keys::constants::v
+                if self.pen_kind == PenKind::Transform
+                    && self.pressing_keys.is_empty() =>
+            {
+                self.flip_floating(false);
+            }
+            gdk::keys::constants::Return
+                if self.canvas.floating().is_some() =>
+            {
+                self.end_transform(true);
+            }
+            gdk::keys::constants::Escape
+                if self.canvas.floating().is_some() =>
+            {
+                self.end_transform(false);
+            }
+            gdk::keys::constants::Return if self.shape_end.is_none() => {
+                self.finish_polygon();
+            }
+            gdk::keys::constants::Escape if !self.shape_points.is_empty() => {
+                self.cancel_shape();
                 (self.draw_handler)();
             }
             _ => {
@@ -243,10 +1222,45 @@ impl Viewport {
     }
 
     pub fn key_release(&mut self, key: gdk::keys::Key) {
+        self.record(Event::KeyRelease(*key));
         self.pressing_keys.remove(&key);
         self.set_pen();
     }
 
+    pub fn release_all_keys(&mut self) {
+        self.record(Event::ReleaseAllKeys);
+        self.pressing_keys.clear();
+        self.set_pen();
+    }
+
+    fn select_pen(&mut self, pen_kind: PenKind) {
+        if !self.shape_points.is_empty() {
+            self.cancel_shape();
+            (self.draw_handler)();
+        }
+        if pen_kind != PenKind::Transform {
+            self.end_transform(true);
+        }
+        if pen_kind != PenKind::Crop {
+            self.end_crop(false);
+        }
+        self.selected_pen_kind = pen_kind;
+        self.set_pen();
+    }
+
+    fn shift_pressed(&self) -> bool {
+        self.pressing_keys.contains(&gdk::keys::constants::Shift_L)
+            || self.pressing_keys.contains(&gdk::keys::constants::Shift_R)
+    }
+
+    fn control_pressed(&self) -> bool {
+        self.pressing_keys
+            .contains(&gdk::keys::constants::Control_L)
+            || self
+                .pressing_keys
+                .contains(&gdk::keys::constants::Control_R)
+    }
+
     fn set_pen(&mut self) {
         if self.pressing_keys
             == [gdk::keys::constants::space].iter().cloned().collect()
@@ -259,12 +1273,360 @@ impl Viewport {
                 .collect()
         {
             self.pen_kind = PenKind::Zoom
+        } else if self.pressing_keys
+            == [gdk::keys::constants::space, gdk::keys::constants::Shift_L]
+                .iter()
+                .cloned()
+                .collect()
+        {
+            self.pen_kind = PenKind::RotateView
+        } else if self.pressing_keys.len() == 1
+            && (self.pressing_keys.contains(&gdk::keys::constants::Alt_L)
+                || self.pressing_keys.contains(&gdk::keys::constants::Alt_R))
+        {
+            self.pen_kind = PenKind::Eyedropper
+        } else if self.eraser_tip {
+            self.pen_kind = PenKind::Eraser
         } else {
-            self.pen_kind = PenKind::Circle
+            self.pen_kind = self.selected_pen_kind
+        }
+    }
+
+    /// Tells whether the events come from the eraser end of a stylus.
+    pub fn set_eraser_tip(&mut self, eraser_tip: bool) {
+        if self.eraser_tip != eraser_tip {
+            self.record(Event::EraserTip(eraser_tip));
+            self.eraser_tip = eraser_tip;
+            self.set_pen();
         }
     }
 
     pub fn set_pen_size(&mut self, size: f64) {
+        self.record(Event::PenSize(size));
         self.canvas.set_pen_size(size);
     }
+
+    pub fn set_pen_opacity(&mut self, opacity: f64) {
+        self.record(Event::PenOpacity(opacity));
+        self.canvas.set_pen_opacity(opacity);
+    }
+
+    pub fn set_pen_antialias(&mut self, antialias: bool) {
+        self.record(Event::Antialias(antialias));
+        self.canvas.set_pen_antialias(antialias);
+    }
+
+    pub fn set_infinite_canvas(&mut self, infinite: bool) {
+        self.record(Event::InfiniteCanvas(infinite));
+        self.canvas.set_infinite(infinite);
+    }
+
+    pub fn set_transform_filter(&mut self, filter: Filter) {
+        self.record(Event::TransformFilter(filter));
+        self.canvas.set_transform_filter(filter);
+    }
+
+    pub fn pressure_curve(&self) -> PressureCurve {
+        self.canvas.pressure_curve()
+    }
+
+    pub fn set_pressure_curve(&mut self, curve: PressureCurve) {
+        self.record(Event::Pressure(curve));
+        self.canvas.set_pressure_curve(curve);
+    }
+
+    pub fn eyedropper_setting(&self) -> (usize, bool) {
+        (self.eyedropper_size, self.eyedropper_merged)
+    }
+
+    /// Sets the width of the sampled square and whether all layers are
+    /// sampled.
+    pub fn set_eyedropper_setting(&mut self, size: usize, merged: bool) {
+        self.record(Event::Eyedropper(size, merged));
+        self.eyedropper_size = size;
+        self.eyedropper_merged = merged;
+    }
+
+    pub fn fill_setting(&self) -> FillSetting {
+        self.canvas.fill_setting()
+    }
+
+    pub fn set_fill_setting(&mut self, setting: FillSetting) {
+        self.record(Event::Fill(setting));
+        self.canvas.set_fill_setting(setting);
+    }
+
+    pub fn set_stabilizer_mode(&mut self, mode: StabilizerMode) {
+        self.record(Event::StabilizerMode(mode));
+        self.stabilizer.mode = mode;
+    }
+
+    pub fn set_stabilizer_strength(&mut self, strength: f64) {
+        self.record(Event::StabilizerStrength(strength));
+        self.stabilizer.strength = strength;
+    }
+
+    pub fn pen_colors(&self) -> (RGB, RGB) {
+        self.canvas.pen_colors()
+    }
+
+    pub fn set_pen_colors(&mut self, foreground: RGB, background: RGB) {
+        self.record(Event::PenColors(foreground, background));
+        self.canvas.set_pen_colors(foreground, background);
+    }
+
+    pub fn swap_pen_colors(&mut self) {
+        self.record(Event::SwapPenColors);
+        self.canvas.swap_pen_colors();
+    }
+
+    pub fn layers(&self) -> &[Layer] {
+        self.canvas.layers()
+    }
+
+    pub fn active_layer(&self) -> usize {
+        self.canvas.active_layer()
+    }
+
+    pub fn set_active_layer(&mut self, index: usize) {
+        self.record(Event::ActiveLayer(index));
+        self.canvas.set_active_layer(index);
+    }
+
+    pub fn add_layer(&mut self) {
+        self.record(Event::AddLayer);
+        self.canvas.add_layer();
+        (self.draw_handler)();
+    }
+
+    pub fn duplicate_layer(&mut self) {
+        self.record(Event::DuplicateLayer);
+        self.canvas.duplicate_layer();
+        (self.draw_handler)();
+    }
+
+    pub fn delete_layer(&mut self) {
+        self.record(Event::DeleteLayer);
+        self.canvas.delete_layer();
+        (self.draw_handler)();
+    }
+
+    pub fn move_layer(&mut self, up: bool) {
+        self.record(Event::MoveLayer(up));
+        self.canvas.move_layer(up);
+        (self.draw_handler)();
+    }
+
+    pub fn set_layer_visible(&mut self, index: usize, visible: bool) {
+        self.record(Event::LayerVisible(index, visible));
+        self.canvas.set_layer_visible(index, visible);
+        (self.draw_handler)();
+    }
+
+    pub fn set_layer_opacity(&mut self, index: usize, opacity: f64) {
+        self.record(Event::LayerOpacity(index, opacity));
+        self.canvas.set_layer_opacity(index, opacity);
+        (self.draw_handler)();
+    }
+
+    pub fn save_png(&self, path: &Path) -> Result<(), Box<dyn Error>> {
+        let mut out = BufWriter::new(File::create(path)?);
+        png::encode(&self.canvas.image.to_image(), &mut out)?;
+        out.flush()?;
+        Ok(())
+    }
+
+    pub fn open_png(&mut self, path: &Path) -> Result<(), Box<dyn Error>> {
+        let image = png::decode(BufReader::new(File::open(path)?))?;
+        self.finish_stroke();
+        self.canvas.set_image(TiledImage::from_image(&image));
+        self.show_whole_canvas();
+        self.record(Event::Open(path.to_owned()));
+        Ok(())
+    }
+
+    /// Resets the view to the center of a canvas that changed its size.
+    fn show_whole_canvas(&mut self) {
+        self.data.borrow_mut().selection_outline.clear();
+        self.data.borrow_mut().crop_area = None;
+        self.data.borrow_mut().canvas_display_matrix =
+            cairo::Matrix::identity();
+        self.center_canvas();
+        self.reflect_all();
+    }
+
+    pub fn canvas_size(&self) -> (usize, usize) {
+        self.canvas.get_size()
+    }
+
+    pub fn new_image(&mut self, size: (usize, usize), background: RGB) {
+        self.record(Event::NewImage(size, background));
+        self.finish_stroke();
+        self.canvas.new_image(size, background);
+        self.show_whole_canvas();
+    }
+
+    pub fn resize_canvas(&mut self, size: (usize, usize), anchor: (f64, f64)) {
+        self.record(Event::ResizeCanvas(size, anchor));
+        self.end_transform(true);
+        self.canvas.resize_canvas(size, anchor);
+        self.show_whole_canvas();
+    }
+
+    pub fn scale_image(&mut self, size: (usize, usize), method: Resample) {
+        self.record(Event::ScaleImage(size, method));
+        self.end_transform(true);
+        self.canvas.scale_image(size, method);
+        self.show_whole_canvas();
+    }
+
+    pub fn set_history_memory_budget(&mut self, memory_budget: usize) {
+        self.canvas.set_history_memory_budget(memory_budget);
+    }
+
+    /// Records the input from now on, or stops recording if `None`.
+    pub fn set_recorder(&mut self, recorder: Option<Recorder>) {
+        self.recorder = recorder;
+    }
+
+    fn record(&mut self, event: Event) {
+        if let Some(recorder) = &mut self.recorder {
+            if let Err(e) = recorder.record(&event) {
+                eprintln!("Recording stopped: {}", e);
+                self.recorder = None;
+            }
+        }
+    }
+
+    /// Feeds a recorded event back as if it came from the user.
+    pub fn replay(&mut self, event: &Event) {
+        match *event {
+            Event::Open(ref path) => {
+                if let Err(e) = self.open_png(path) {
+                    eprintln!("Cannot open {}: {}", path.display(), e);
+                }
+            }
+            Event::Pen(input) => self.pen_stroke(input),
+            Event::StrokeEnd => self.pen_stroke_end(),
+            Event::EraserTip(e) => self.set_eraser_tip(e),
+            Event::KeyPress(k) => self.key_press(gdk::keys::Key::from_glib(k)),
+            Event::KeyRelease(k) => {
+                self.key_release(gdk::keys::Key::from_glib(k))
+            }
+            Event::ReleaseAllKeys => self.release_all_keys(),
+            Event::Resize(width, height) => {
+                self.set_viewport_size(width, height);
+                self.reflect_all();
+            }
+            Event::CenterCanvas => self.set_canvas_center(),
+            Event::Zoom(position, ds) => self.zoom_at(position, ds),
+            Event::Scroll(dx, dy) => self.scroll(dx, dy),
+            Event::PenSize(size) => self.set_pen_size(size),
+            Event::PenOpacity(opacity) => self.set_pen_opacity(opacity),
+            Event::Antialias(antialias) => self.set_pen_antialias(antialias),
+            Event::InfiniteCanvas(infinite) => {
+                self.set_infinite_canvas(infinite)
+            }
+            Event::TransformFilter(filter) => self.set_transform_filter(filter),
+            Event::Pressure(curve) => self.set_pressure_curve(curve),
+            Event::Eyedropper(size, merged) => {
+                self.set_eyedropper_setting(size, merged)
+            }
+            Event::Fill(setting) => self.set_fill_setting(setting),
+            Event::StabilizerMode(mode) => self.set_stabilizer_mode(mode),
+            Event::StabilizerStrength(strength) => {
+                self.set_stabilizer_strength(strength)
+            }
+            Event::PenColors(foreground, background) => {
+                self.set_pen_colors(foreground, background)
+            }
+            Event::SwapPenColors => self.swap_pen_colors(),
+            Event::ActiveLayer(index) => self.set_active_layer(index),
+            Event::AddLayer => self.add_layer(),
+            Event::DuplicateLayer => self.duplicate_layer(),
+            Event::DeleteLayer => self.delete_layer(),
+            Event::MoveLayer(up) => self.move_layer(up),
+            Event::LayerVisible(index, visible) => {
+                self.set_layer_visible(index, visible)
+            }
+            Event::LayerOpacity(index, opacity) => {
+                self.set_layer_opacity(index, opacity)
+            }
+            Event::NewImage(size, background) => {
+                self.new_image(size, background)
+            }
+            Event::ResizeCanvas(size, anchor) => {
+                self.resize_canvas(size, anchor)
+            }
+            Event::ScaleImage(size, method) => self.scale_image(size, method),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use simple_paint::session;
+    use std::io::{self, Write};
+
+    /// A writer whose output can be read while the recorder owns it.
+    #[derive(Clone, Default)]
+    struct Shared(Rc<RefCell<Vec<u8>>>);
+
+    impl Write for Shared {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.borrow_mut().write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    /// A 400 x 300 viewport drawing on an image surface, which needs no
+    /// display.
+    fn viewport() -> Viewport {
+        let surface =
+            cairo::ImageSurface::create(cairo::Format::ARgb32, 400, 300)
+                .unwrap();
+        let context =
+            Rc::new(RefCell::new(Some(cairo::Context::new(&surface))));
+        Viewport::new(context, (400, 300), Box::new(|| ()))
+    }
+
+    #[test]
+    fn test_replay_zoom_and_stroke() {
+        let out = Shared::default();
+        let mut recorded = viewport();
+        recorded.set_recorder(Some(Recorder::new(Box::new(out.clone()))));
+        recorded.set_viewport_size(400, 300);
+        recorded.new_image((200, 100), RGB::new(0xff, 0xff, 0xff));
+        recorded.zoom_at((200.0, 150.0), 4.0);
+        recorded.set_pen_size(2.0);
+        recorded.set_pen_antialias(false);
+        for &x in &[150.0, 250.0] {
+            recorded.pen_stroke(PenInput {
+                x,
+                y: 150.0,
+                pressure: 1.0,
+            });
+        }
+        recorded.pen_stroke_end();
+
+        let text = String::from_utf8(out.0.borrow().clone()).unwrap();
+        let mut replayed = viewport();
+        for (_, e) in session::parse(&text).unwrap() {
+            replayed.replay(&e);
+        }
+        let image = replayed.canvas.image.to_image();
+        assert_eq!(image.vector, recorded.canvas.image.to_image().vector);
+        // The center of the canvas is at the center of the viewport, where
+        // the zoom keeps it, so the stroke spans 25 canvas pixels around it
+        // instead of 100.
+        let (black, white) = (RGB::new(0, 0, 0), RGB::new(0xff, 0xff, 0xff));
+        assert_eq!(image.get(90, 50), black);
+        assert_eq!(image.get(110, 50), black);
+        assert_eq!(image.get(70, 50), white);
+        assert_eq!(image.get(130, 50), white);
+    }
 }